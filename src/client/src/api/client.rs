@@ -0,0 +1,187 @@
+use super::rate_limit::{RateLimit, RateLimiter};
+use crate::error::Error;
+use crate::metrics;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Proxy, Url};
+use serde::de::DeserializeOwned;
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_USER_AGENT: &str = concat!("lol-replay-client/", env!("CARGO_PKG_VERSION"));
+
+// Shared by every spectator request so connections are reused across them
+#[derive(Debug, Clone)]
+pub struct SpectatorClient {
+    http: reqwest::Client,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl SpectatorClient {
+    pub fn builder() -> SpectatorClientBuilder {
+        SpectatorClientBuilder::default()
+    }
+
+    pub(crate) async fn get_text(&self, platform_id: &str, url: &str) -> Result<String, Error> {
+        self.get(platform_id, url, |response| response.text()).await
+    }
+
+    pub(crate) async fn get_json<T: DeserializeOwned>(
+        &self,
+        platform_id: &str,
+        url: &str,
+    ) -> Result<T, Error> {
+        self.get(platform_id, url, |response| response.json()).await
+    }
+
+    pub(crate) async fn get_bytes(&self, platform_id: &str, url: &str) -> Result<Vec<u8>, Error> {
+        let bytes = self
+            .get(platform_id, url, |response| response.bytes())
+            .await?;
+        Ok(bytes.to_vec())
+    }
+
+    // The permit is kept until the body is read for the request to count as in flight,
+    // the latency is measured from then so waiting for the rate limiter does not count
+    async fn get<T, F, Fut>(&self, platform_id: &str, url: &str, read: F) -> Result<T, Error>
+    where
+        F: FnOnce(reqwest::Response) -> Fut,
+        Fut: Future<Output = reqwest::Result<T>>,
+    {
+        let _permit = match &self.rate_limiter {
+            Some(rate_limiter) => Some(rate_limiter.acquire(&host(url)).await),
+            None => None,
+        };
+
+        let start = Instant::now();
+        let response = self.http.get(url).send().await;
+        let status = match &response {
+            Ok(response) => response.status().as_str().to_string(),
+            Err(_) => "error".to_string(),
+        };
+        let result = match response.and_then(|response| response.error_for_status()) {
+            Ok(response) => read(response).await,
+            Err(error) => Err(error),
+        };
+        metrics::observe_request(platform_id, &status, start.elapsed());
+
+        Ok(result?)
+    }
+}
+
+fn host(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(url) => format!(
+            "{}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port_or_known_default().unwrap_or_default()
+        ),
+        Err(_) => url.to_string(),
+    }
+}
+
+#[derive(Debug)]
+pub struct SpectatorClientBuilder {
+    timeout: Duration,
+    connect_timeout: Duration,
+    pool_idle_timeout: Duration,
+    pool_max_idle_per_host: usize,
+    proxy: Option<String>,
+    user_agent: String,
+    headers: HeaderMap,
+    rate_limit: Option<RateLimit>,
+}
+
+impl Default for SpectatorClientBuilder {
+    fn default() -> Self {
+        SpectatorClientBuilder {
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            pool_idle_timeout: Duration::from_secs(90),
+            pool_max_idle_per_host: 8,
+            proxy: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            headers: HeaderMap::new(),
+            rate_limit: Some(RateLimit::default()),
+        }
+    }
+}
+
+impl SpectatorClientBuilder {
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    pub fn pool_idle_timeout(mut self, pool_idle_timeout: Duration) -> Self {
+        self.pool_idle_timeout = pool_idle_timeout;
+        self
+    }
+
+    pub fn pool_max_idle_per_host(mut self, pool_max_idle_per_host: usize) -> Self {
+        self.pool_max_idle_per_host = pool_max_idle_per_host;
+        self
+    }
+
+    // Proxy used for every request, e.g. http://127.0.0.1:3128 or socks5://127.0.0.1:1080
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    // None disables rate limiting, only meant for hosts we control
+    pub fn rate_limit(mut self, rate_limit: Option<RateLimit>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    pub fn build(self) -> Result<SpectatorClient, Error> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .user_agent(self.user_agent)
+            .default_headers(self.headers);
+
+        if let Some(proxy) = self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+
+        Ok(SpectatorClient {
+            http: builder.build()?,
+            rate_limiter: self.rate_limit.map(RateLimiter::new),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host() {
+        assert_eq!(
+            host("http://spectator-consumer.kr.lol.pvp.net:80/observer-mode/rest/consumer/version"),
+            "spectator-consumer.kr.lol.pvp.net:80"
+        );
+        assert_eq!(host("https://127.0.0.1/version"), "127.0.0.1:443");
+        assert_eq!(host("not an url"), "not an url");
+    }
+}
@@ -1,22 +1,29 @@
+use super::client::SpectatorClient;
 use super::models::{ChunkInfo, GameMetaData, SpectatorEndpoint};
+use crate::error::Error;
+
 use log::debug;
-use reqwest;
+use reqwest::StatusCode;
 
-pub async fn fetch_api_version(endpoint: &SpectatorEndpoint) -> Result<String, reqwest::Error> {
+pub async fn fetch_api_version(
+    client: &SpectatorClient,
+    endpoint: &SpectatorEndpoint,
+) -> Result<String, Error> {
     let url = format!("{}/observer-mode/rest/consumer/version", endpoint.base_url);
 
     debug!("Fetching API version from URL: {}", url);
 
-    let response: String = reqwest::get(&url).await?.error_for_status()?.text().await?;
+    let response: String = client.get_text(&endpoint.platform_id, &url).await?;
 
     debug!("Received API version response: {}", response);
     Ok(response.to_string())
 }
 
 pub async fn fetch_game_meta_data(
+    client: &SpectatorClient,
     endpoint: &SpectatorEndpoint,
     game_id: &str,
-) -> Result<GameMetaData, reqwest::Error> {
+) -> Result<GameMetaData, Error> {
     let url = format!(
         "{base_url}/observer-mode/rest/consumer/getGameMetaData/{platform_id}/{game_id}/1/token",
         base_url = endpoint.base_url,
@@ -25,7 +32,10 @@ pub async fn fetch_game_meta_data(
     );
     debug!("Fetching API game meta data from URL: {}", url);
 
-    let response: GameMetaData = reqwest::get(&url).await?.error_for_status()?.json().await?;
+    let response: GameMetaData = client
+        .get_json(&endpoint.platform_id, &url)
+        .await
+        .map_err(|error| game_not_found(error, endpoint, game_id))?;
 
     debug!("Received API game meta data response: {}", response);
 
@@ -33,9 +43,10 @@ pub async fn fetch_game_meta_data(
 }
 
 pub async fn fetch_last_chunk_info(
+    client: &SpectatorClient,
     endpoint: &SpectatorEndpoint,
     game_id: &str,
-) -> Result<ChunkInfo, reqwest::Error> {
+) -> Result<ChunkInfo, Error> {
     let url = format!(
         "{base_url}/observer-mode/rest/consumer/getLastChunkInfo/{platform_id}/{game_id}/0/token",
         base_url = endpoint.base_url,
@@ -44,7 +55,10 @@ pub async fn fetch_last_chunk_info(
     );
     debug!("Fetching API last chunk info data from URL: {}", url);
 
-    let response: ChunkInfo = reqwest::get(&url).await?.error_for_status()?.json().await?;
+    let response: ChunkInfo = client
+        .get_json(&endpoint.platform_id, &url)
+        .await
+        .map_err(|error| game_not_found(error, endpoint, game_id))?;
 
     debug!("Received API last chunk info response: {}", response);
 
@@ -52,10 +66,11 @@ pub async fn fetch_last_chunk_info(
 }
 
 pub async fn fetch_game_data_chunk(
+    client: &SpectatorClient,
     endpoint: &SpectatorEndpoint,
     game_id: &str,
     chunk_id: u32,
-) -> Result<Vec<u8>, reqwest::Error> {
+) -> Result<Vec<u8>, Error> {
     let url = format!(
         "{base_url}/observer-mode/rest/consumer/getGameDataChunk/{platform_id}/{game_id}/{chunk_id}/token",
         base_url = endpoint.base_url,
@@ -65,19 +80,19 @@ pub async fn fetch_game_data_chunk(
     );
     debug!("Fetching API game data chunk from URL: {}", url);
 
-    let response = reqwest::get(url).await?.error_for_status()?;
+    let bytes = client.get_bytes(&endpoint.platform_id, &url).await?;
 
     debug!("Received API game data chunk");
 
-    let bytes = response.bytes().await?;
-    Ok(bytes.to_vec())
+    Ok(bytes)
 }
 
 pub async fn fetch_keyframe(
+    client: &SpectatorClient,
     endpoint: &SpectatorEndpoint,
     game_id: &str,
     keyframe_id: u32,
-) -> Result<Vec<u8>, reqwest::Error> {
+) -> Result<Vec<u8>, Error> {
     let url = format!(
         "{base_url}/observer-mode/rest/consumer/getKeyFrame/{platform_id}/{game_id}/{keyframe_id}/token",
         base_url = endpoint.base_url,
@@ -87,12 +102,25 @@ pub async fn fetch_keyframe(
     );
     debug!("Fetching API keyframe from URL: {}", url);
 
-    let response = reqwest::get(url).await?.error_for_status()?;
+    let bytes = client.get_bytes(&endpoint.platform_id, &url).await?;
 
     debug!("Received API keyframe");
 
-    let bytes = response.bytes().await?;
-    Ok(bytes.to_vec())
+    Ok(bytes)
+}
+
+// The spectator API answers 404 for games it does not host or no longer hosts
+fn game_not_found(error: Error, endpoint: &SpectatorEndpoint, game_id: &str) -> Error {
+    match error {
+        Error::Status {
+            status: StatusCode::NOT_FOUND,
+            ..
+        } => Error::GameNotFound {
+            platform_id: endpoint.platform_id.clone(),
+            game_id: game_id.to_string(),
+        },
+        error => error,
+    }
 }
 
 // TODO write endOfGameStats and featured endpoints
@@ -101,6 +129,7 @@ pub async fn fetch_keyframe(
 mod tests {
     use super::*;
     use mockito::Server;
+    use reqwest::header::{HeaderName, HeaderValue};
 
     fn init() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -120,7 +149,36 @@ mod tests {
             platform_id: "KR".to_string(),
         };
 
-        let version = fetch_api_version(&endpoint).await.unwrap();
+        let client = SpectatorClient::builder().build().unwrap();
+        let version = fetch_api_version(&client, &endpoint).await.unwrap();
+        assert_eq!(version, "2.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_api_version_client_headers() {
+        init();
+        let mut server = Server::new_async().await;
+        let _m = server
+            .mock("GET", "/observer-mode/rest/consumer/version")
+            .match_header("user-agent", "replay-tests")
+            .match_header("x-custom", "custom value")
+            .with_body("2.0.0")
+            .create();
+
+        let endpoint = SpectatorEndpoint {
+            base_url: server.url(),
+            platform_id: "KR".to_string(),
+        };
+        let client = SpectatorClient::builder()
+            .user_agent("replay-tests")
+            .header(
+                HeaderName::from_static("x-custom"),
+                HeaderValue::from_static("custom value"),
+            )
+            .build()
+            .unwrap();
+
+        let version = fetch_api_version(&client, &endpoint).await.unwrap();
         assert_eq!(version, "2.0.0");
     }
 
@@ -144,7 +202,8 @@ mod tests {
             platform_id: "KR".to_string(),
         };
 
-        let result = fetch_game_meta_data(&endpoint, "6654667050").await;
+        let client = SpectatorClient::builder().build().unwrap();
+        let result = fetch_game_meta_data(&client, &endpoint, "6654667050").await;
 
         assert!(result.is_ok());
         let response = result.unwrap();
@@ -152,6 +211,51 @@ mod tests {
         assert_eq!(response.game_key.platform_id, "KR");
     }
 
+    #[tokio::test]
+    async fn test_fetch_game_meta_data_not_found() {
+        init();
+        let mut server = Server::new_async().await;
+        let _m = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameMetaData/KR/6654667050/1/token",
+            )
+            .with_status(404)
+            .create();
+
+        let endpoint = SpectatorEndpoint {
+            base_url: server.url(),
+            platform_id: "KR".to_string(),
+        };
+
+        let client = SpectatorClient::builder().build().unwrap();
+        let result = fetch_game_meta_data(&client, &endpoint, "6654667050").await;
+        assert!(matches!(result, Err(Error::GameNotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_game_meta_data_invalid_body() {
+        init();
+        let mut server = Server::new_async().await;
+        let _m = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameMetaData/KR/6654667050/1/token",
+            )
+            .with_status(200)
+            .with_body("{}")
+            .create();
+
+        let endpoint = SpectatorEndpoint {
+            base_url: server.url(),
+            platform_id: "KR".to_string(),
+        };
+
+        let client = SpectatorClient::builder().build().unwrap();
+        let result = fetch_game_meta_data(&client, &endpoint, "6654667050").await;
+        assert!(matches!(result, Err(Error::Decode(_))));
+    }
+
     #[tokio::test]
     async fn test_fetch_last_chunk_info() {
         init();
@@ -167,7 +271,8 @@ mod tests {
             platform_id: "KR".to_string(),
         };
 
-        let result = fetch_last_chunk_info(&endpoint, "6654667050").await;
+        let client = SpectatorClient::builder().build().unwrap();
+        let result = fetch_last_chunk_info(&client, &endpoint, "6654667050").await;
         assert!(result.is_ok());
 
         let chunk_info = result.unwrap();
@@ -195,7 +300,8 @@ mod tests {
             platform_id: "KR".to_string(),
         };
 
-        let result = fetch_game_data_chunk(&endpoint, "6654667050", 1).await;
+        let client = SpectatorClient::builder().build().unwrap();
+        let result = fetch_game_data_chunk(&client, &endpoint, "6654667050", 1).await;
         assert!(result.is_ok());
     }
 
@@ -219,7 +325,36 @@ mod tests {
             platform_id: "KR".to_string(),
         };
 
-        let result = fetch_keyframe(&endpoint, "6654667050", 1).await;
+        let client = SpectatorClient::builder().build().unwrap();
+        let result = fetch_keyframe(&client, &endpoint, "6654667050", 1).await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_fetch_keyframe_server_error() {
+        init();
+        let mut server = Server::new_async().await;
+        let _m = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getKeyFrame/KR/6654667050/1/token",
+            )
+            .with_status(500)
+            .create();
+
+        let endpoint = SpectatorEndpoint {
+            base_url: server.url(),
+            platform_id: "KR".to_string(),
+        };
+
+        let client = SpectatorClient::builder().build().unwrap();
+        let result = fetch_keyframe(&client, &endpoint, "6654667050", 1).await;
+        assert!(matches!(
+            result,
+            Err(Error::Status {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                ..
+            })
+        ));
+    }
 }
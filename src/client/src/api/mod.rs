@@ -1,3 +1,5 @@
+pub mod client;
 pub mod endpoints;
 pub mod models;
+pub mod rate_limit;
 pub mod utils;
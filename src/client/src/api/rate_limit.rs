@@ -0,0 +1 @@
+pub use lol_replay_common::rate_limit::{RateLimit, RateLimiter};
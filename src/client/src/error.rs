@@ -0,0 +1,102 @@
+use reqwest::StatusCode;
+
+use std::fmt;
+use std::io;
+use std::process::ExitCode;
+
+#[derive(Debug)]
+pub enum Error {
+    // The spectator host could not be reached or the request timed out
+    Network(reqwest::Error),
+    // The spectator host answered with an unexpected status
+    Status {
+        url: String,
+        status: StatusCode,
+    },
+    // A response, record or replay file could not be decoded
+    Decode(Box<dyn std::error::Error + Send + Sync>),
+    Storage(io::Error),
+    GameNotFound {
+        platform_id: String,
+        game_id: String,
+    },
+    // The records database could not be reached or rejected a query
+    Database(Box<dyn std::error::Error + Send + Sync>),
+    // The config file or the settings it is combined with are invalid
+    Config(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Error::Network(_) => ExitCode::from(3),
+            Error::Status { .. } => ExitCode::from(4),
+            Error::Decode(_) => ExitCode::from(5),
+            Error::Storage(_) => ExitCode::from(6),
+            Error::GameNotFound { .. } => ExitCode::from(7),
+            Error::Database(_) => ExitCode::from(8),
+            Error::Config(_) => ExitCode::from(2),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network(error) => write!(f, "network error: {}", error),
+            Error::Status { url, status } => write!(f, "{} returned status {}", url, status),
+            Error::Decode(error) => write!(f, "decode error: {}", error),
+            Error::Storage(error) => write!(f, "storage error: {}", error),
+            Error::GameNotFound {
+                platform_id,
+                game_id,
+            } => write!(f, "game {} not found on platform {}", game_id, platform_id),
+            Error::Database(error) => write!(f, "database error: {}", error),
+            Error::Config(error) => write!(f, "config error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network(error) => Some(error),
+            Error::Decode(error) => Some(error.as_ref()),
+            Error::Storage(error) => Some(error),
+            Error::Database(error) | Error::Config(error) => Some(error.as_ref()),
+            Error::Status { .. } | Error::GameNotFound { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        if let Some(status) = error.status() {
+            Error::Status {
+                url: error.url().map(|url| url.to_string()).unwrap_or_default(),
+                status,
+            }
+        } else if error.is_decode() {
+            Error::Decode(Box::new(error))
+        } else {
+            Error::Network(error)
+        }
+    }
+}
+
+// Invalid data is reported as a decode error, anything else comes from the storage
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::InvalidData {
+            Error::Decode(Box::new(error))
+        } else {
+            Error::Storage(error)
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Decode(Box::new(error))
+    }
+}
@@ -0,0 +1,9 @@
+// Records League of Legends games from the spectator API and turns the records into replays.
+// The lol-replay-client binary is a command line front end over this library.
+pub mod api;
+pub mod error;
+pub mod metrics;
+pub mod recording;
+pub mod replay;
+
+pub use error::Error;
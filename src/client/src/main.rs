@@ -1,67 +1,592 @@
-mod api;
-mod recording;
+use lol_replay_client::api::client::{SpectatorClient, DEFAULT_USER_AGENT};
+use lol_replay_client::api::models::SpectatorEndpoint;
+use lol_replay_client::api::rate_limit::RateLimit;
+use lol_replay_client::api::utils::Region;
+use lol_replay_client::metrics;
+use lol_replay_client::recording::events::RecordingEvent;
+use lol_replay_client::recording::models::Record;
+use lol_replay_client::recording::process;
+use lol_replay_client::recording::process::RecordingOptions;
+use lol_replay_client::recording::sink::{DatabaseSink, DirectorySink, RecordSink, StorageSink};
+use lol_replay_client::recording::storage::{game_folder, DiskStorage, Storage};
+use lol_replay_client::replay::crypto;
+use lol_replay_client::replay::packets::PacketReader;
+use lol_replay_client::replay::rofl;
+use lol_replay_client::Error;
+use lol_replay_common::config::Config;
+use lol_replay_common::record::StoredRecord;
 
-use api::models::SpectatorEndpoint;
-use api::utils::Region;
-use recording::process;
-use recording::storage::DiskStorage;
-
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand};
+use log::{error, info};
+use reqwest::header::{HeaderName, HeaderValue};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
 
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
 
+// Without a subcommand the flags are those of record, as before subcommands existed
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(author, version, about, subcommand_negates_reqs = true)]
 struct Cli {
+    // TOML config file, flags take precedence over it and LOL_REPLAY_* variables override it
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    record: Option<RecordArgs>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Record a game from a spectator endpoint
+    Record(Box<RecordArgs>),
+    /// Decrypt and decompress the chunks and keyframes of a recorded game
+    Decrypt(DecryptArgs),
+    /// List the packets of the game data chunks of a recorded game
+    Packets(StoredRecordArgs),
+    /// Import a .rofl replay file as a recorded game
+    Import(ImportArgs),
+    /// Rewrite record files written by older versions in the current format
+    Upgrade(UpgradeArgs),
+}
+
+#[derive(Args, Debug)]
+struct RecordArgs {
     // Using official league of legend spectator endpoint
     #[arg(long)]
     region_endpoint: Option<Region>,
 
     // Using custom league of legend spectator endpoint
     #[command(flatten)]
-    custom_endpoint: Option<CustomEndpoint>,
+    custom_endpoint: CustomEndpoint,
 
-    #[arg(long)]
+    // Cli::record is only set when its group has a member, clap leaves the group of a struct
+    // with flattened fields empty
+    #[arg(long, group = "RecordArgs")]
     game_id: String,
 
-    #[arg(long)]
+    #[arg(long, group = "RecordArgs")]
     encryption_key: String,
 
+    // Falls back to storage.record_folder of the config
     #[arg(long)]
-    record_folder: PathBuf,
+    record_folder: Option<PathBuf>,
+
+    #[command(flatten)]
+    sink: SinkArgs,
+
+    #[command(flatten)]
+    http: HttpArgs,
+
+    // Number of earlier chunks and keyframes downloaded at the same time
+    #[arg(long)]
+    backfill_concurrency: Option<usize>,
+
+    // Seconds between two attempts to fetch the last chunk info after an error
+    #[arg(long)]
+    retry_delay: Option<u64>,
+
+    // Consecutive errors before the recording is given up, unlimited by default
+    #[arg(long)]
+    max_retries: Option<u32>,
+
+    // Seconds in-flight downloads may run after SIGINT or SIGTERM before the record is saved,
+    // falls back to recording.shutdown_timeout of the config
+    #[arg(long)]
+    shutdown_timeout: Option<u64>,
+
+    // Export the game as a .rofl replay file once recorded
+    #[arg(long)]
+    rofl_output: Option<PathBuf>,
+
+    // Print each recording progress event on stdout
+    #[arg(long)]
+    progress: bool,
+
+    // Serve Prometheus metrics on GET /metrics at this address, e.g. 127.0.0.1:9100
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+}
+
+#[derive(Args, Debug)]
+struct HttpArgs {
+    // Spectator request timeout in seconds, this flag and the next three fall back to the [http]
+    // section of the config
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    // Spectator connection timeout in seconds
+    #[arg(long)]
+    connect_timeout: Option<u64>,
+
+    // Seconds an idle pooled connection is kept alive
+    #[arg(long)]
+    pool_idle_timeout: Option<u64>,
+
+    #[arg(long)]
+    pool_max_idle_per_host: Option<usize>,
+
+    // Proxy used for spectator requests, e.g. socks5://127.0.0.1:1080
+    #[arg(long)]
+    proxy: Option<String>,
+
+    #[arg(long, default_value = DEFAULT_USER_AGENT)]
+    user_agent: String,
+
+    // Maximum requests per second to a spectator host
+    #[arg(long)]
+    requests_per_second: Option<u32>,
+
+    // Maximum concurrent requests to a spectator host
+    #[arg(long)]
+    max_in_flight: Option<usize>,
+
+    // Extra header sent with every spectator request, as "Name: value"
+    #[arg(long = "header", value_parser = parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
 }
 
 #[derive(Args, Debug)]
 #[group(conflicts_with = "region_endpoint", multiple = true, required = false)]
 struct CustomEndpoint {
+    #[arg(long, requires = "platform_id")]
+    base_url: Option<String>,
+    #[arg(long, requires = "base_url")]
+    platform_id: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct StoredRecordArgs {
+    #[arg(long)]
+    platform_id: String,
+
+    #[arg(long)]
+    game_id: String,
+
+    // Replaces the encryption key of the record file
+    #[arg(long)]
+    encryption_key: Option<String>,
+
+    #[arg(long)]
+    record_folder: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct DecryptArgs {
+    #[command(flatten)]
+    record: StoredRecordArgs,
+
+    // Folder receiving the decrypted game data chunks and keyframes
     #[arg(long)]
-    base_url: String,
+    output_folder: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct ImportArgs {
+    #[arg(long)]
+    rofl: PathBuf,
+
+    // .rofl files do not contain the platform the game was played on
     #[arg(long)]
     platform_id: String,
+
+    #[arg(long)]
+    record_folder: PathBuf,
+
+    #[command(flatten)]
+    sink: SinkArgs,
+}
+
+// Record files are written in the record folder unless one of these, or storage.records_dir
+// or database.url of the config, is given
+#[derive(Args, Debug)]
+#[group(multiple = false)]
+struct SinkArgs {
+    // Folder receiving the record files as {platform_id}/{game_id}.json
+    #[arg(long)]
+    records_dir: Option<PathBuf>,
+
+    // Database receiving the record files, e.g. records.sqlite
+    #[arg(long)]
+    database_url: Option<String>,
+}
+
+impl SinkArgs {
+    fn to_sink(&self, config: &Config) -> Result<Box<dyn RecordSink>, Error> {
+        // A flag replaces the configured sink instead of adding to it
+        let (records_dir, database_url) =
+            if self.records_dir.is_some() || self.database_url.is_some() {
+                (&self.records_dir, &self.database_url)
+            } else {
+                (&config.storage.records_dir, &config.database.url)
+            };
+
+        if let Some(records_dir) = records_dir {
+            Ok(Box::new(DirectorySink::new(records_dir.clone())))
+        } else if let Some(database_url) = database_url {
+            Ok(Box::new(DatabaseSink::connect(database_url)?))
+        } else {
+            Ok(Box::new(StorageSink))
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct UpgradeArgs {
+    // Record files upgraded in place
+    #[arg(required = true)]
+    records: Vec<PathBuf>,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), reqwest::Error> {
+async fn main() -> ExitCode {
     let args = Cli::parse();
 
     env_logger::init();
 
-    let endpoint = if let Some(region_endpoint) = args.region_endpoint {
-        region_endpoint.to_endpoint()
-    } else {
-        let custom_endpoint = args.custom_endpoint.unwrap();
-        SpectatorEndpoint::new(custom_endpoint.base_url, custom_endpoint.platform_id)
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            error.exit_code()
+        }
+    }
+}
+
+async fn run(args: Cli) -> Result<(), Error> {
+    let config =
+        Config::load(args.config.as_deref()).map_err(|error| Error::Config(Box::new(error)))?;
+
+    let command = match (args.command, args.record) {
+        (Some(command), None) => command,
+        (None, Some(record)) => Command::Record(Box::new(record)),
+        (Some(_), Some(_)) => {
+            return Err(Error::Config(
+                "record flags cannot be used with a subcommand".into(),
+            ))
+        }
+        (None, None) => {
+            return Err(Error::Config(
+                "--game-id and --encryption-key are required".into(),
+            ))
+        }
     };
+    match command {
+        Command::Record(args) => {
+            let rofl_output = args.rofl_output.clone();
+            let (events, printer) = args.progress.then(print_progress).unzip();
+            let result = record(*args, &config, events).await.and_then(|record| {
+                if let Some(path) = rofl_output {
+                    rofl::export(&record, BufWriter::new(File::create(path)?))?;
+                }
+                Ok(())
+            });
+            // The record is dropped with its sender, the printer ends once every event is printed
+            if let Some(printer) = printer {
+                let _ = printer.await;
+            }
+            result?;
+        }
+        Command::Decrypt(args) => decrypt(args)?,
+        Command::Packets(args) => packets(args)?,
+        Command::Import(args) => import(args, &config)?,
+        Command::Upgrade(args) => upgrade(args)?,
+    }
 
-    let storage = DiskStorage::new(args.record_folder.join(&endpoint.platform_id)).unwrap();
+    Ok(())
+}
+
+async fn record(
+    args: RecordArgs,
+    config: &Config,
+    events: Option<mpsc::UnboundedSender<RecordingEvent>>,
+) -> Result<Arc<Record>, Error> {
+    let endpoint = spectator_endpoint(args.region_endpoint, args.custom_endpoint, config)?;
+
+    let record_folder = args
+        .record_folder
+        .or_else(|| config.storage.record_folder.clone())
+        .ok_or_else(|| {
+            Error::Config("--record-folder or storage.record_folder is required".into())
+        })?;
+    let storage = DiskStorage::new(game_folder(
+        &record_folder,
+        &endpoint.platform_id,
+        &args.game_id,
+    ))?;
+
+    let defaults = RecordingOptions::default();
+    let rate_limit = RateLimit::default();
+
+    let mut client = SpectatorClient::builder()
+        .user_agent(args.http.user_agent)
+        .rate_limit(Some(RateLimit {
+            requests_per_second: args
+                .http
+                .requests_per_second
+                .or(config.concurrency.requests_per_second)
+                .unwrap_or(rate_limit.requests_per_second),
+            max_in_flight: args
+                .http
+                .max_in_flight
+                .or(config.concurrency.max_in_flight)
+                .unwrap_or(rate_limit.max_in_flight),
+        }));
+    // Unset in both, the defaults of the builder apply
+    let http = &config.http;
+    if let Some(timeout) = args.http.timeout.or(http.timeout) {
+        client = client.timeout(Duration::from_secs(timeout));
+    }
+    if let Some(connect_timeout) = args.http.connect_timeout.or(http.connect_timeout) {
+        client = client.connect_timeout(Duration::from_secs(connect_timeout));
+    }
+    if let Some(pool_idle_timeout) = args.http.pool_idle_timeout.or(http.pool_idle_timeout) {
+        client = client.pool_idle_timeout(Duration::from_secs(pool_idle_timeout));
+    }
+    if let Some(pool_max_idle_per_host) = args
+        .http
+        .pool_max_idle_per_host
+        .or(http.pool_max_idle_per_host)
+    {
+        client = client.pool_max_idle_per_host(pool_max_idle_per_host);
+    }
+    if let Some(proxy) = args.http.proxy {
+        client = client.proxy(proxy);
+    }
+    for (name, value) in args.http.headers {
+        client = client.header(name, value);
+    }
+
+    if let Some(metrics_addr) = args.metrics_addr {
+        tokio::spawn(async move {
+            if let Err(error) = metrics::serve(metrics_addr).await {
+                error!("Metrics endpoint on {} stopped: {}", metrics_addr, error);
+            }
+        });
+    }
 
     process::new(
+        client.build()?,
         endpoint,
         args.game_id,
         args.encryption_key,
         Box::new(storage),
+        args.sink.to_sink(config)?,
+        RecordingOptions {
+            backfill_concurrency: args
+                .backfill_concurrency
+                .or(config.concurrency.backfill)
+                .unwrap_or(defaults.backfill_concurrency),
+            shutdown_timeout: args
+                .shutdown_timeout
+                .or(config.recording.shutdown_timeout)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.shutdown_timeout),
+            retry_delay: args
+                .retry_delay
+                .or(config.retry.delay)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.retry_delay),
+            max_retries: args.max_retries.or(config.retry.max_retries),
+            events,
+        },
+        shutdown_signal(),
+    )
+    .await
+}
+
+// Flags first, then the [spectator] section of the config
+fn spectator_endpoint(
+    region_endpoint: Option<Region>,
+    custom_endpoint: CustomEndpoint,
+    config: &Config,
+) -> Result<SpectatorEndpoint, Error> {
+    if let Some(region_endpoint) = region_endpoint {
+        return Ok(region_endpoint.to_endpoint());
+    }
+    if let (Some(base_url), Some(platform_id)) =
+        (custom_endpoint.base_url, custom_endpoint.platform_id)
+    {
+        return Ok(SpectatorEndpoint::new(base_url, platform_id));
+    }
+
+    let spectator = &config.spectator;
+    match (
+        &spectator.region,
+        &spectator.base_url,
+        &spectator.platform_id,
+    ) {
+        (Some(region), _, _) => Ok(region
+            .to_lowercase()
+            .parse::<Region>()
+            .map_err(|error| Error::Config(error.into()))?
+            .to_endpoint()),
+        (None, Some(base_url), Some(platform_id)) => Ok(SpectatorEndpoint::new(
+            base_url.clone(),
+            platform_id.clone(),
+        )),
+        _ => Err(Error::Config(
+            "missing spectator endpoint, set --region-endpoint or [spectator] in the config".into(),
+        )),
+    }
+}
+
+fn print_progress() -> (mpsc::UnboundedSender<RecordingEvent>, JoinHandle<()>) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let printer = tokio::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            println!("{}", event);
+        }
+    });
+    (sender, printer)
+}
+
+// Flip to true on the first SIGINT or SIGTERM
+fn shutdown_signal() -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("Shutdown requested, saving the record");
+        let _ = sender.send(true);
+    });
+    receiver
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        }
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = header.split_once(':').ok_or_else(|| {
+        format!(
+            "'{}' is not a valid header, expected \"Name: value\"",
+            header
+        )
+    })?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| e.to_string())?;
+    let value = HeaderValue::from_str(value.trim()).map_err(|e| e.to_string())?;
+    Ok((name, value))
+}
+
+// The record file lists the chunks and keyframes of the game, games recorded before each got its
+// own folder share theirs with the other games of the platform
+fn load_stored_record(args: StoredRecordArgs) -> Result<Record, Error> {
+    let folders = [
+        game_folder(&args.record_folder, &args.platform_id, &args.game_id),
+        args.record_folder.join(&args.platform_id),
+    ];
+    for folder in folders {
+        if !folder.is_dir() {
+            continue;
+        }
+        let storage = DiskStorage::open(folder);
+        let json = match storage.load_record(&args.game_id) {
+            Ok(json) => json,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error.into()),
+        };
+        let mut stored_record = StoredRecord::from_json(&String::from_utf8_lossy(&json))?;
+        // The record folder may have been moved since the game was recorded
+        stored_record.storage = storage.descriptor();
+        if let Some(encryption_key) = args.encryption_key {
+            stored_record.encryption_key = encryption_key;
+        }
+        return Ok(Record::from_stored_record(stored_record)?);
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!(
+            "no record of game {} in {}",
+            args.game_id,
+            args.record_folder.join(&args.platform_id).display()
+        ),
     )
-    .await?;
+    .into())
+}
+
+fn decrypt(args: DecryptArgs) -> Result<(), Error> {
+    let record = load_stored_record(args.record)?;
+    let decrypted = crypto::decrypt_record(&record)?;
+
+    let output = DiskStorage::new(args.output_folder)?;
+    for (chunk_id, data) in decrypted.game_data_chunks {
+        output.store_game_data_chunk(chunk_id, data)?;
+    }
+    for (keyframe_id, data) in decrypted.keyframes {
+        output.store_key_frame(keyframe_id, data)?;
+    }
+
+    Ok(())
+}
+
+fn packets(args: StoredRecordArgs) -> Result<(), Error> {
+    let record = load_stored_record(args)?;
+    let decrypted = crypto::decrypt_record(&record)?;
+
+    for (chunk_id, data) in decrypted.game_data_chunks {
+        for packet in PacketReader::new(data.as_slice()) {
+            let packet = packet?;
+            println!(
+                "chunk {} time {:.3} type {:#06x} params {:#010x} length {}",
+                chunk_id,
+                packet.timestamp,
+                packet.packet_type,
+                packet.params,
+                packet.payload.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn import(args: ImportArgs, config: &Config) -> Result<(), Error> {
+    let mut reader = BufReader::new(File::open(args.rofl)?);
+    let record_folder = args.record_folder;
+    let platform_id = args.platform_id;
+    let record = rofl::import(&mut reader, platform_id.clone(), |game_id| {
+        let storage = DiskStorage::new(game_folder(&record_folder, &platform_id, game_id))?;
+        Ok(Box::new(storage) as Box<dyn Storage>)
+    })?;
+    args.sink.to_sink(config)?.save(&record)?;
+
+    Ok(())
+}
+
+fn upgrade(args: UpgradeArgs) -> Result<(), Error> {
+    for path in args.records {
+        let stored_record = StoredRecord::from_json(&fs::read_to_string(&path)?)?;
+        fs::write(&path, serde_json::to_string(&stored_record)?)?;
+        info!("Upgraded {}", path.display());
+    }
 
     Ok(())
 }
@@ -0,0 +1,171 @@
+use crate::recording::events::{RecordingEvent, RecordingEventKind};
+
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+static GAME_DATA_CHUNKS_DOWNLOADED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lol_replay_game_data_chunks_downloaded_total",
+        "Game data chunks downloaded and stored",
+        &["platform_id"]
+    )
+    .unwrap()
+});
+
+static KEYFRAMES_DOWNLOADED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lol_replay_keyframes_downloaded_total",
+        "Keyframes downloaded and stored",
+        &["platform_id"]
+    )
+    .unwrap()
+});
+
+static BYTES_STORED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lol_replay_bytes_stored_total",
+        "Bytes of media data written to the storage",
+        &["platform_id", "media_type"]
+    )
+    .unwrap()
+});
+
+static RETRIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lol_replay_retries_total",
+        "Last chunk info requests retried after an error",
+        &["platform_id"]
+    )
+    .unwrap()
+});
+
+static ACTIVE_RECORDINGS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "lol_replay_active_recordings",
+        "Games currently being recorded",
+        &["platform_id"]
+    )
+    .unwrap()
+});
+
+static REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "lol_replay_spectator_request_duration_seconds",
+        "Spectator request latency, from sending the request to reading the body",
+        &["platform_id", "status"]
+    )
+    .unwrap()
+});
+
+// Status is the HTTP status code, or "error" when no response was received
+pub fn observe_request(platform_id: &str, status: &str, duration: Duration) {
+    REQUEST_DURATION
+        .with_label_values(&[platform_id, status])
+        .observe(duration.as_secs_f64());
+}
+
+pub fn observe_event(event: &RecordingEvent) {
+    let platform_id = event.platform_id.as_str();
+    match &event.kind {
+        RecordingEventKind::GameDataChunkStored { size, .. } => {
+            GAME_DATA_CHUNKS_DOWNLOADED
+                .with_label_values(&[platform_id])
+                .inc();
+            BYTES_STORED
+                .with_label_values(&[platform_id, "game_data_chunk"])
+                .inc_by(*size as u64);
+        }
+        RecordingEventKind::KeyFrameStored { size, .. } => {
+            KEYFRAMES_DOWNLOADED.with_label_values(&[platform_id]).inc();
+            BYTES_STORED
+                .with_label_values(&[platform_id, "keyframe"])
+                .inc_by(*size as u64);
+        }
+        RecordingEventKind::Retry { .. } => RETRIES.with_label_values(&[platform_id]).inc(),
+        _ => {}
+    }
+}
+
+pub fn recording_started(platform_id: &str) {
+    ACTIVE_RECORDINGS.with_label_values(&[platform_id]).inc();
+}
+
+pub fn recording_stopped(platform_id: &str) {
+    ACTIVE_RECORDINGS.with_label_values(&[platform_id]).dec();
+}
+
+// Every registered metric in the Prometheus text format
+pub fn gather() -> Vec<u8> {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("metrics are encodable");
+    buffer
+}
+
+// Serves GET /metrics until the server fails
+pub async fn serve(addr: SocketAddr) -> Result<(), hyper::Error> {
+    let make_service =
+        make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle_request)) });
+    Server::try_bind(&addr)?.serve(make_service).await
+}
+
+async fn handle_request(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let mut response = Response::default();
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                TextEncoder::new().format_type().parse().unwrap(),
+            );
+            *response.body_mut() = Body::from(gather());
+        }
+        _ => *response.status_mut() = StatusCode::NOT_FOUND,
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        observe_event(&RecordingEvent {
+            platform_id: "EUW1".to_string(),
+            game_id: "6654667050".to_string(),
+            kind: RecordingEventKind::KeyFrameStored {
+                keyframe_id: 1,
+                size: 128,
+            },
+        });
+
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let response = handle_request(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#"lol_replay_keyframes_downloaded_total{platform_id="EUW1"} 1"#));
+        assert!(body.contains(
+            r#"lol_replay_bytes_stored_total{media_type="keyframe",platform_id="EUW1"} 128"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_path() {
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        let response = handle_request(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
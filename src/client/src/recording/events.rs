@@ -0,0 +1,121 @@
+use super::models::RecordStatus;
+
+use std::fmt;
+use std::ops::Range;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingEvent {
+    pub platform_id: String,
+    pub game_id: String,
+    pub kind: RecordingEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordingEventKind {
+    MetadataFetched,
+    GameDataChunkStored {
+        chunk_id: u32,
+        size: usize,
+    },
+    KeyFrameStored {
+        keyframe_id: u32,
+        size: usize,
+    },
+    // Earlier media data missing from the record, it is backfilled
+    GapDetected {
+        chunk_ids: Range<u32>,
+        keyframe_ids: Range<u32>,
+    },
+    Retry {
+        error: String,
+        delay: Duration,
+    },
+    GameEnded {
+        last_chunk_id: u32,
+    },
+    RecordSaved {
+        status: RecordStatus,
+    },
+}
+
+impl fmt::Display for RecordingEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}: ", self.platform_id, self.game_id)?;
+        match &self.kind {
+            RecordingEventKind::MetadataFetched => write!(f, "metadata fetched"),
+            RecordingEventKind::GameDataChunkStored { chunk_id, size } => {
+                write!(f, "game data chunk {} stored ({} bytes)", chunk_id, size)
+            }
+            RecordingEventKind::KeyFrameStored { keyframe_id, size } => {
+                write!(f, "keyframe {} stored ({} bytes)", keyframe_id, size)
+            }
+            RecordingEventKind::GapDetected {
+                chunk_ids,
+                keyframe_ids,
+            } => write!(
+                f,
+                "gap detected, chunks {:?} and keyframes {:?} are backfilled",
+                chunk_ids, keyframe_ids
+            ),
+            RecordingEventKind::Retry { error, delay } => {
+                write!(f, "retry in {:?} after error: {}", delay, error)
+            }
+            RecordingEventKind::GameEnded { last_chunk_id } => {
+                write!(f, "game ended at chunk {}", last_chunk_id)
+            }
+            RecordingEventKind::RecordSaved { status } => {
+                write!(f, "record saved with status {:?}", status)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::models::SpectatorEndpoint;
+    use crate::recording::models::Record;
+    use crate::recording::storage::DiskStorage;
+
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_emit() {
+        let folder = tempfile::tempdir().unwrap();
+        let mut record = Record::new(
+            String::new(),
+            SpectatorEndpoint::new(String::new(), "KR".to_string()),
+            "6654667050".to_string(),
+            String::new(),
+            Box::new(DiskStorage::new(folder.path().to_path_buf()).unwrap()),
+        );
+        // Without a listener events are dropped
+        record.emit(RecordingEventKind::MetadataFetched);
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        record.events = Some(sender);
+        record.emit(RecordingEventKind::GameDataChunkStored {
+            chunk_id: 3,
+            size: 42,
+        });
+
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(
+            event,
+            RecordingEvent {
+                platform_id: "KR".to_string(),
+                game_id: "6654667050".to_string(),
+                kind: RecordingEventKind::GameDataChunkStored {
+                    chunk_id: 3,
+                    size: 42
+                },
+            }
+        );
+        assert_eq!(
+            event.to_string(),
+            "KR/6654667050: game data chunk 3 stored (42 bytes)"
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -1,3 +1,5 @@
+pub mod events;
 pub mod models;
 pub mod process;
+pub mod sink;
 pub mod storage;
@@ -1,18 +1,23 @@
 use crate::api::models::{GameMetaData, SpectatorEndpoint};
+use crate::metrics;
 
+use super::events::{RecordingEvent, RecordingEventKind};
 use super::storage::Storage;
 
-use serde::ser::SerializeStruct;
-use serde::{Deserialize, Serialize, Serializer};
+use lol_replay_common::record::{StoredRecord, FORMAT_VERSION};
+use serde::{Serialize, Serializer};
 
 use std::collections::HashSet;
 use std::fmt;
-use std::fs;
 use std::io;
 use std::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
+
+pub use lol_replay_common::record::RecordStatus;
 
 pub struct Record {
     pub version: String,
+    pub game_version: Option<String>,
     pub endpoint: SpectatorEndpoint,
     pub game_id: String,
     pub encryption_key: String,
@@ -20,6 +25,8 @@ pub struct Record {
     pub keyframes: Mutex<HashSet<u32>>,
     pub game_data_chunks: Mutex<HashSet<u32>>,
     pub storage: Box<dyn Storage>,
+    pub status: Mutex<RecordStatus>,
+    pub events: Option<UnboundedSender<RecordingEvent>>,
 }
 
 impl Record {
@@ -32,6 +39,7 @@ impl Record {
     ) -> Self {
         Record {
             version,
+            game_version: None,
             endpoint,
             game_id,
             encryption_key,
@@ -39,6 +47,8 @@ impl Record {
             keyframes: Mutex::new(HashSet::new()),
             game_data_chunks: Mutex::new(HashSet::new()),
             storage,
+            status: Mutex::new(RecordStatus::Recording),
+            events: None,
         }
     }
 
@@ -58,14 +68,60 @@ impl Record {
         self.keyframes.lock().unwrap().insert(chunk_id);
     }
 
-    pub fn save_to_file(&self) -> Result<(), io::Error> {
-        fs::create_dir_all(format!("./completed/{}", self.endpoint.platform_id))?;
-        let filename = format!(
-            "./completed/{}/{}.json",
-            self.endpoint.platform_id, self.game_id
+    pub fn status(&self) -> RecordStatus {
+        *self.status.lock().unwrap()
+    }
+
+    pub fn set_status(&self, status: RecordStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+
+    // Observers going away must not interrupt the recording
+    pub fn emit(&self, kind: RecordingEventKind) {
+        let event = RecordingEvent {
+            platform_id: self.endpoint.platform_id.clone(),
+            game_id: self.game_id.clone(),
+            kind,
+        };
+        metrics::observe_event(&event);
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    // Reopens the storage the record was written with, for reading
+    pub fn from_stored_record(stored_record: StoredRecord) -> Result<Self, io::Error> {
+        let storage = stored_record.storage.open_read_only()?;
+        let mut record = Record::new(
+            stored_record.version,
+            stored_record.endpoint,
+            stored_record.game_id,
+            stored_record.encryption_key,
+            storage,
         );
-        let json = serde_json::to_string(&self).unwrap();
-        fs::write(filename, json)
+        record.game_version = stored_record.game_version;
+        record.metadata = stored_record.metadata;
+        record.keyframes = Mutex::new(stored_record.keyframes.into_iter().collect());
+        record.game_data_chunks = Mutex::new(stored_record.game_data_chunks.into_iter().collect());
+        record.status = Mutex::new(stored_record.status);
+        Ok(record)
+    }
+
+    // Snapshot of the record in the format shared with the server
+    pub fn to_stored_record(&self) -> StoredRecord {
+        StoredRecord {
+            format_version: FORMAT_VERSION,
+            version: self.version.clone(),
+            game_version: self.game_version.clone(),
+            endpoint: self.endpoint.clone(),
+            game_id: self.game_id.clone(),
+            encryption_key: self.encryption_key.clone(),
+            metadata: self.metadata.clone(),
+            keyframes: sorted_ids(&self.keyframes),
+            game_data_chunks: sorted_ids(&self.game_data_chunks),
+            storage: self.storage.descriptor(),
+            status: self.status(),
+        }
     }
 }
 
@@ -74,51 +130,62 @@ impl Serialize for Record {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("Record", 8)?;
-
-        state.serialize_field("version", &self.version)?;
-        state.serialize_field("endpoint", &self.endpoint)?;
-        state.serialize_field("game_id", &self.game_id)?;
-        state.serialize_field("encryption_key", &self.encryption_key)?;
-        state.serialize_field("metadata", &self.metadata)?;
-
-        // Sorting keyframes in ascending order
-        state.serialize_field("keyframes", &{
-            let mut sorted = self
-                .keyframes
-                .lock()
-                .unwrap()
-                .iter()
-                .cloned()
-                .collect::<Vec<_>>();
-            sorted.sort();
-            sorted
-        })?;
-
-        // Sorting game_data_chunks in ascending order
-        state.serialize_field("game_data_chunks", &{
-            let mut sorted = self
-                .game_data_chunks
-                .lock()
-                .unwrap()
-                .iter()
-                .cloned()
-                .collect::<Vec<_>>();
-            sorted.sort();
-            sorted
-        })?;
-
-        state.serialize_field("storage", &self.storage.metadata())?;
-        state.end()
+        self.to_stored_record().serialize(serializer)
     }
 }
 
+fn sorted_ids(ids: &Mutex<HashSet<u32>>) -> Vec<u32> {
+    let mut sorted = ids.lock().unwrap().iter().cloned().collect::<Vec<_>>();
+    sorted.sort();
+    sorted
+}
+
 impl fmt::Display for Record {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Version: {}\n", self.version)?;
-        write!(f, "Game Id: {}\n", self.game_id)?;
-        write!(f, "Encryption Key: {}\n", self.game_id)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Game Id: {}", self.game_id)?;
+        writeln!(f, "Encryption Key: {}", self.game_id)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::storage::DiskStorage;
+
+    #[test]
+    fn test_stored_record_round_trip() {
+        let folder = tempfile::tempdir().unwrap();
+        let record = Record::new(
+            "2.0.0".to_string(),
+            SpectatorEndpoint::new("http://127.0.0.1".to_string(), "KR".to_string()),
+            "6654667050".to_string(),
+            "oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6".to_string(),
+            Box::new(DiskStorage::new(folder.path().to_path_buf()).unwrap()),
+        );
+        record.insert_game_data_chunk(2);
+        record.insert_game_data_chunk(1);
+        record.insert_keyframe(1);
+        record.set_status(RecordStatus::Interrupted);
+
+        let json = serde_json::to_string(&record).unwrap();
+        let stored_record = StoredRecord::from_json(&json).unwrap();
+        assert_eq!(stored_record.game_data_chunks, vec![1, 2]);
+
+        let loaded = Record::from_stored_record(stored_record).unwrap();
+        assert_eq!(loaded.game_id, record.game_id);
+        assert!(loaded.has_game_data_chunk(2));
+        assert!(loaded.has_keyframe(1));
+        assert_eq!(loaded.status(), RecordStatus::Interrupted);
+        assert_eq!(loaded.storage.descriptor(), record.storage.descriptor());
+
+        // Loading a record does not create the folders of a storage that was moved away
+        let mut moved = StoredRecord::from_json(&json).unwrap();
+        let missing = folder.path().join("moved");
+        moved.storage = DiskStorage::open(missing.clone()).descriptor();
+        assert!(Record::from_stored_record(moved).is_ok());
+        assert!(!missing.exists());
+    }
+}
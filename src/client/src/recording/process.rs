@@ -1,57 +1,131 @@
+use crate::api::client::SpectatorClient;
 use crate::api::endpoints;
-use crate::api::models::SpectatorEndpoint;
+use crate::api::models::{GameMetaData, SpectatorEndpoint};
+use crate::error::Error;
+use crate::metrics;
 
-use super::models::Record;
+use super::events::{RecordingEvent, RecordingEventKind};
+use super::models::{Record, RecordStatus};
+use super::sink::RecordSink;
 use super::storage::Storage;
 
+use chrono::NaiveDateTime;
 use log::debug;
 use tokio::spawn;
-use tokio::time::{sleep, Duration};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout_at, Duration, Instant};
 
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 
+#[derive(Debug, Clone)]
+pub struct RecordingOptions {
+    // Number of earlier chunks and keyframes downloaded at the same time
+    pub backfill_concurrency: usize,
+    // How long in-flight downloads may run once a shutdown is requested
+    pub shutdown_timeout: Duration,
+    // Wait between two attempts to fetch the last chunk info
+    pub retry_delay: Duration,
+    // Consecutive failed attempts before giving up on the game, None retries forever
+    pub max_retries: Option<u32>,
+    // Receives the progress of the recording, if anyone is listening
+    pub events: Option<UnboundedSender<RecordingEvent>>,
+}
+
+impl Default for RecordingOptions {
+    fn default() -> Self {
+        RecordingOptions {
+            backfill_concurrency: 4,
+            shutdown_timeout: Duration::from_secs(30),
+            retry_delay: Duration::from_secs(10),
+            max_retries: None,
+            events: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MediaData {
+    GameDataChunk(u32),
+    KeyFrame(u32),
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn new(
+    client: SpectatorClient,
     endpoint: SpectatorEndpoint,
     game_id: String,
     encryption_key: String,
     storage: Box<dyn Storage>,
-) -> Result<(), reqwest::Error> {
-    let version = endpoints::fetch_api_version(&endpoint).await?;
+    sink: Box<dyn RecordSink>,
+    options: RecordingOptions,
+    shutdown: watch::Receiver<bool>,
+) -> Result<Arc<Record>, Error> {
+    let version = endpoints::fetch_api_version(&client, &endpoint).await?;
     let mut record = Record::new(version, endpoint, game_id, encryption_key, storage);
+    record.events = options.events.clone();
 
-    let metadata = endpoints::fetch_game_meta_data(&record.endpoint, &record.game_id).await?;
+    let metadata =
+        endpoints::fetch_game_meta_data(&client, &record.endpoint, &record.game_id).await?;
     record.metadata = Some(metadata);
+    record.emit(RecordingEventKind::MetadataFetched);
 
     let arc_record = Arc::new(record);
 
-    record_media_data(arc_record).await?;
+    record_media_data(client, arc_record.clone(), sink, options, shutdown).await?;
 
-    Ok(())
+    Ok(arc_record)
 }
 
-async fn record_media_data(record: Arc<Record>) -> Result<(), reqwest::Error> {
+async fn record_media_data(
+    client: SpectatorClient,
+    record: Arc<Record>,
+    sink: Box<dyn RecordSink>,
+    options: RecordingOptions,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), Error> {
     let endpoint = record.endpoint.clone();
     let game_id = record.game_id.clone();
     let mut tasks = Vec::new();
     let mut current_chunk_id = 1;
     let mut current_keyframe_id = 1;
+    let mut result = Ok(());
+    let mut interrupted = false;
+    let mut retries = 0;
+    metrics::recording_started(&endpoint.platform_id);
 
     loop {
-        match endpoints::fetch_last_chunk_info(&endpoint, &game_id).await {
+        let chunk_info = tokio::select! {
+            chunk_info = endpoints::fetch_last_chunk_info(&client, &endpoint, &game_id) => chunk_info,
+            _ = shutdown_requested(&mut shutdown) => {
+                interrupted = true;
+                break;
+            }
+        };
+
+        match chunk_info {
             Ok(chunk_info) => {
+                retries = 0;
                 if chunk_info.chunk_id != current_chunk_id
                     || chunk_info.key_frame_id != current_keyframe_id
                 {
                     debug!("Received first chunk info but there is a gap between chunk_id or keyframe_id try to download previous media data");
-                    let record_clone = record.clone();
-                    let process_previous_media_data_task = spawn(async move {
-                        let _ = process_previous_media_data(
-                            record_clone,
-                            chunk_info.chunk_id,
-                            chunk_info.key_frame_id,
-                        )
-                        .await;
+                    record.emit(RecordingEventKind::GapDetected {
+                        chunk_ids: current_chunk_id..chunk_info.chunk_id,
+                        keyframe_ids: current_keyframe_id..chunk_info.key_frame_id,
                     });
+                    let record_clone = record.clone();
+                    let process_previous_media_data_task = spawn(process_previous_media_data(
+                        client.clone(),
+                        record_clone,
+                        options.clone(),
+                        current_chunk_id..chunk_info.chunk_id,
+                        current_keyframe_id..chunk_info.key_frame_id,
+                        shutdown.clone(),
+                    ));
                     tasks.push(process_previous_media_data_task);
 
                     current_chunk_id = chunk_info.chunk_id;
@@ -59,19 +133,20 @@ async fn record_media_data(record: Arc<Record>) -> Result<(), reqwest::Error> {
                 }
 
                 let record_clone = record.clone();
-                let process_media_data_task = spawn(async move {
-                    let _ = process_media_data(
-                        record_clone,
-                        chunk_info.chunk_id,
-                        chunk_info.key_frame_id,
-                    )
-                    .await;
-                });
+                let process_media_data_task = spawn(process_media_data(
+                    client.clone(),
+                    record_clone,
+                    chunk_info.chunk_id,
+                    chunk_info.key_frame_id,
+                ));
 
                 tasks.push(process_media_data_task);
 
                 if chunk_info.chunk_id == chunk_info.end_game_chunk_id {
                     debug!("Received last chunk info");
+                    record.emit(RecordingEventKind::GameEnded {
+                        last_chunk_id: chunk_info.chunk_id,
+                    });
                     break;
                 }
 
@@ -81,76 +156,310 @@ async fn record_media_data(record: Arc<Record>) -> Result<(), reqwest::Error> {
                 let waiting_time = Duration::from_millis(chunk_info.next_available_chunk as u64)
                     + Duration::from_secs(1);
                 debug!("Wait {:?} milliseconds before next iteration", waiting_time);
-                sleep(waiting_time).await;
+                if !sleep_unless_shutdown(waiting_time, &mut shutdown).await {
+                    interrupted = true;
+                    break;
+                }
+            }
+            Err(error @ Error::GameNotFound { .. }) => {
+                debug!("Record Frames received error {} stop recording", error);
+                result = Err(error);
+                break;
+            }
+            Err(error) if options.max_retries.is_some_and(|max| retries >= max) => {
+                debug!("Record Frames received error {} giving up", error);
+                result = Err(error);
+                break;
             }
             Err(error) => {
+                retries += 1;
+                let delay = options.retry_delay;
                 debug!(
-                    "Record Frames received error {} retry in 10 seconds...",
-                    error
+                    "Record Frames received error {} retry in {:?}...",
+                    error, delay
                 );
-                sleep(Duration::from_secs(10)).await;
-                continue;
+                record.emit(RecordingEventKind::Retry {
+                    error: error.to_string(),
+                    delay,
+                });
+                if !sleep_unless_shutdown(delay, &mut shutdown).await {
+                    interrupted = true;
+                    break;
+                }
             }
         }
     }
 
-    debug!("Awaiting for tasks");
-    for task in tasks {
-        let _ = task.await;
+    if interrupted {
+        debug!(
+            "Shutdown requested, waiting up to {:?} for in-flight downloads",
+            options.shutdown_timeout
+        );
+    } else {
+        debug!("Awaiting for tasks");
     }
-    debug!("Saving record to json");
-    record.save_to_file().unwrap();
+    let deadline = Instant::now() + options.shutdown_timeout;
+    for mut task in tasks {
+        let task_result = if interrupted {
+            match timeout_at(deadline, &mut task).await {
+                Ok(task_result) => task_result,
+                Err(_) => {
+                    debug!("Aborting media data task after shutdown timeout");
+                    task.abort();
+                    continue;
+                }
+            }
+        } else {
+            task.await
+        };
 
-    Ok(())
+        // Failed downloads leave a gap in the record, failing to store is fatal
+        match task_result {
+            Ok(Err(error @ Error::Storage(_))) if result.is_ok() => result = Err(error),
+            Ok(Err(error)) => debug!("Media data task failed: {}", error),
+            Err(error) => debug!("Media data task panicked: {}", error),
+            Ok(Ok(())) => {}
+        }
+    }
+
+    metrics::recording_stopped(&endpoint.platform_id);
+    record.set_status(if interrupted {
+        RecordStatus::Interrupted
+    } else if result.is_err() {
+        RecordStatus::Failed
+    } else {
+        RecordStatus::Complete
+    });
+    debug!("Saving record");
+    sink.save(&record)?;
+    record.emit(RecordingEventKind::RecordSaved {
+        status: record.status(),
+    });
+
+    result
+}
+
+// Resolves once a shutdown is requested, never if none can be requested anymore
+async fn shutdown_requested(shutdown: &mut watch::Receiver<bool>) {
+    if shutdown.wait_for(|requested| *requested).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+// Returns false if a shutdown was requested before the end of the sleep
+async fn sleep_unless_shutdown(duration: Duration, shutdown: &mut watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        _ = sleep(duration) => true,
+        _ = shutdown_requested(shutdown) => false,
+    }
 }
 
 async fn process_previous_media_data(
+    client: SpectatorClient,
     record: Arc<Record>,
-    current_chunk_id: u32,
-    current_key_frame_id: u32,
-) -> Result<(), reqwest::Error> {
-    for chunk_id in (1..=current_chunk_id - 1).rev() {
-        let _ = fetch_and_store_game_data_chunk(record.clone(), chunk_id).await;
+    options: RecordingOptions,
+    chunk_ids: Range<u32>,
+    keyframe_ids: Range<u32>,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(), Error> {
+    // The startup and game chunk ids may not have been published yet when recording started
+    let metadata =
+        match endpoints::fetch_game_meta_data(&client, &record.endpoint, &record.game_id).await {
+            Ok(metadata) => Some(metadata),
+            Err(error) => {
+                debug!(
+                    "Backfilling with the metadata fetched at startup: {}",
+                    error
+                );
+                record.metadata.clone()
+            }
+        };
+    let queue = backfill_queue(metadata.as_ref(), chunk_ids, keyframe_ids);
+    debug!("Backfilling {} earlier media data", queue.len());
+    let queue = Arc::new(Mutex::new(queue));
+
+    let mut workers = JoinSet::new();
+    for _ in 0..options.backfill_concurrency.max(1) {
+        workers.spawn(backfill_worker(
+            client.clone(),
+            record.clone(),
+            queue.clone(),
+            shutdown.clone(),
+        ));
     }
 
-    for keyframe_id in (1..=current_key_frame_id - 1).rev() {
-        let _ = fetch_and_store_keyframe(record.clone(), keyframe_id).await;
+    let mut result = Ok(());
+    while let Some(worker) = workers.join_next().await {
+        match worker {
+            Ok(Err(error)) if result.is_ok() => result = Err(error),
+            Err(error) => debug!("Backfill worker panicked: {}", error),
+            _ => {}
+        }
     }
 
-    Ok(())
+    result
+}
+
+// Earlier media data existing on the spectator host, the ones closest to expiry first.
+// Startup chunks are kept for the whole game while game chunks and keyframes expire oldest first.
+fn backfill_queue(
+    metadata: Option<&GameMetaData>,
+    chunk_ids: Range<u32>,
+    keyframe_ids: Range<u32>,
+) -> VecDeque<MediaData> {
+    let (end_startup_chunk_id, start_game_chunk_id) = metadata
+        .map(|metadata| (metadata.end_startup_chunk_id, metadata.start_game_chunk_id))
+        .unwrap_or((0, 1));
+
+    let startup_chunks = chunk_ids
+        .clone()
+        .filter(|chunk_id| *chunk_id <= end_startup_chunk_id)
+        .map(MediaData::GameDataChunk)
+        .collect::<Vec<_>>();
+
+    let game_media_data = match metadata {
+        Some(metadata)
+            if !metadata.pending_available_chunk_info.is_empty()
+                || !metadata.pending_available_key_frame_info.is_empty() =>
+        {
+            pending_media_data(metadata, chunk_ids, keyframe_ids)
+        }
+        _ => guessed_media_data(start_game_chunk_id, chunk_ids, keyframe_ids),
+    };
+
+    game_media_data.into_iter().chain(startup_chunks).collect()
+}
+
+// The game chunks and keyframes the spectator host announced, by the time it received them
+fn pending_media_data(
+    metadata: &GameMetaData,
+    chunk_ids: Range<u32>,
+    keyframe_ids: Range<u32>,
+) -> Vec<MediaData> {
+    // Keyframes come before the chunk received at the same time
+    let mut pending = metadata
+        .pending_available_key_frame_info
+        .iter()
+        .filter(|info| keyframe_ids.contains(&info.key_frame_id))
+        .map(|info| (&info.received_time, MediaData::KeyFrame(info.key_frame_id)))
+        .chain(
+            metadata
+                .pending_available_chunk_info
+                .iter()
+                .filter(|info| {
+                    chunk_ids.contains(&info.chunk_id)
+                        && info.chunk_id > metadata.end_startup_chunk_id
+                })
+                .map(|info| (&info.received_time, MediaData::GameDataChunk(info.chunk_id))),
+        )
+        .map(|(received_time, media_data)| (received_at(received_time), media_data))
+        .collect::<Vec<_>>();
+    // Unreadable times last
+    pending.sort_by_key(|(received_at, _)| (received_at.is_none(), *received_at));
+
+    pending
+        .into_iter()
+        .map(|(_, media_data)| media_data)
+        .collect()
+}
+
+// e.g. "Aug 15, 2023 8:17:42 PM"
+fn received_at(received_time: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(received_time, "%b %d, %Y %I:%M:%S %p").ok()
+}
+
+// Without announced media data, keyframes are ordered right before the chunk that follows them
+fn guessed_media_data(
+    start_game_chunk_id: u32,
+    chunk_ids: Range<u32>,
+    keyframe_ids: Range<u32>,
+) -> Vec<MediaData> {
+    let mut game_media_data = chunk_ids
+        .filter(|chunk_id| *chunk_id >= start_game_chunk_id)
+        .map(|chunk_id| ((chunk_id, 1), MediaData::GameDataChunk(chunk_id)))
+        .chain(keyframe_ids.map(|keyframe_id| {
+            (
+                ((start_game_chunk_id + 2 * keyframe_id).saturating_sub(1), 0),
+                MediaData::KeyFrame(keyframe_id),
+            )
+        }))
+        .collect::<Vec<_>>();
+    game_media_data.sort_by_key(|(position, _)| *position);
+
+    game_media_data
+        .into_iter()
+        .map(|(_, media_data)| media_data)
+        .collect()
+}
+
+async fn backfill_worker(
+    client: SpectatorClient,
+    record: Arc<Record>,
+    queue: Arc<Mutex<VecDeque<MediaData>>>,
+    shutdown: watch::Receiver<bool>,
+) -> Result<(), Error> {
+    loop {
+        // Only the download in progress is finished once a shutdown is requested
+        if *shutdown.borrow() {
+            debug!("Shutdown requested, leaving the rest of the backfill");
+            return Ok(());
+        }
+        let media_data = match queue.lock().unwrap().pop_front() {
+            Some(media_data) => media_data,
+            None => return Ok(()),
+        };
+
+        let result = match media_data {
+            MediaData::GameDataChunk(chunk_id) => {
+                fetch_and_store_game_data_chunk(&client, record.clone(), chunk_id).await
+            }
+            MediaData::KeyFrame(keyframe_id) => {
+                fetch_and_store_keyframe(&client, record.clone(), keyframe_id).await
+            }
+        };
+
+        match result {
+            Err(error @ Error::Storage(_)) => return Err(error),
+            Err(error) => debug!("Unable to backfill {:?}: {}", media_data, error),
+            Ok(()) => {}
+        }
+    }
 }
 
 async fn process_media_data(
+    client: SpectatorClient,
     record: Arc<Record>,
     chunk_id: u32,
     keyframe_id: u32,
-) -> Result<(), reqwest::Error> {
-    let _ = fetch_and_store_game_data_chunk(record.clone(), chunk_id).await;
-    let _ = fetch_and_store_keyframe(record, keyframe_id).await;
+) -> Result<(), Error> {
+    let chunk_result = fetch_and_store_game_data_chunk(&client, record.clone(), chunk_id).await;
+    let keyframe_result = fetch_and_store_keyframe(&client, record, keyframe_id).await;
 
-    Ok(())
+    chunk_result.and(keyframe_result)
 }
 
 async fn fetch_and_store_game_data_chunk(
+    client: &SpectatorClient,
     record: Arc<Record>,
     chunk_id: u32,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), Error> {
     // Return if the chunk ID is already in the set
     if record.has_game_data_chunk(chunk_id) {
         return Ok(());
     }
 
-    match endpoints::fetch_game_data_chunk(&record.endpoint, &record.game_id, chunk_id).await {
+    match endpoints::fetch_game_data_chunk(client, &record.endpoint, &record.game_id, chunk_id)
+        .await
+    {
         Ok(game_data_chunk) => {
             debug!("Storing game data chunk id {}", chunk_id);
-            if let Err(e) = record
+            let size = game_data_chunk.len();
+            record
                 .storage
                 .store_game_data_chunk(chunk_id, game_data_chunk)
-            {
-                debug!("Error while storing chunk: {}", e);
-            } else {
-                record.insert_game_data_chunk(chunk_id);
-            }
+                .map_err(Error::Storage)?;
+            record.insert_game_data_chunk(chunk_id);
+            record.emit(RecordingEventKind::GameDataChunkStored { chunk_id, size });
         }
         Err(error) => {
             debug!("error {}", error);
@@ -161,22 +470,25 @@ async fn fetch_and_store_game_data_chunk(
 }
 
 async fn fetch_and_store_keyframe(
+    client: &SpectatorClient,
     record: Arc<Record>,
     keyframe_id: u32,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), Error> {
     // Return if the keyframe ID is already in the set
     if record.has_keyframe(keyframe_id) {
         return Ok(());
     }
 
-    match endpoints::fetch_keyframe(&record.endpoint, &record.game_id, keyframe_id).await {
+    match endpoints::fetch_keyframe(client, &record.endpoint, &record.game_id, keyframe_id).await {
         Ok(keyframe) => {
             debug!("Storing keyframe {}", keyframe_id);
-            if let Err(e) = record.storage.store_key_frame(keyframe_id, keyframe) {
-                debug!("Error while storing keyframe: {}", e);
-            } else {
-                record.insert_keyframe(keyframe_id);
-            }
+            let size = keyframe.len();
+            record
+                .storage
+                .store_key_frame(keyframe_id, keyframe)
+                .map_err(Error::Storage)?;
+            record.insert_keyframe(keyframe_id);
+            record.emit(RecordingEventKind::KeyFrameStored { keyframe_id, size });
         }
         Err(error) => {
             debug!("error {}", error);
@@ -185,3 +497,254 @@ async fn fetch_and_store_keyframe(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::sink::StorageSink;
+    use crate::recording::storage::DiskStorage;
+
+    use lol_replay_common::record::StoredRecord;
+    use mockito::Server;
+
+    const METADATA: &str = r#"{"gameKey":{"gameId":6654667050,"platformId":"KR"},"gameServerAddress":"","port":0,"encryptionKey":"","chunkTimeInterval":30000,"startTime":"","gameEnded":false,"lastChunkId":39,"lastKeyFrameId":19,"endStartupChunkId":1,"delayTime":180000,"pendingAvailableChunkInfo":[],"pendingAvailableKeyFrameInfo":[],"keyFrameTimeInterval":60000,"decodedEncryptionKey":"","startGameChunkId":2,"gameLength":0,"clientAddedLag":0,"clientBackFetchingEnabled":false,"clientBackFetchingFreq":1000,"interestScore":0,"featuredGame":false,"createTime":"","endGameChunkId":-1,"endGameKeyFrameId":-1}"#;
+
+    // The host still serves chunks 8 to 12 and keyframes 4 to 6, listed out of order
+    const PENDING_METADATA: &str = r#"{"gameKey":{"gameId":6654667050,"platformId":"KR"},"gameServerAddress":"","port":0,"encryptionKey":"","chunkTimeInterval":30000,"startTime":"Aug 15, 2023 9:55:42 AM","gameEnded":false,"lastChunkId":12,"lastKeyFrameId":6,"endStartupChunkId":1,"delayTime":180000,"pendingAvailableChunkInfo":[{"chunkId":10,"duration":30026,"receivedTime":"Aug 15, 2023 10:00:42 AM"},{"chunkId":8,"duration":30020,"receivedTime":"Aug 15, 2023 9:59:42 AM"},{"chunkId":9,"duration":29987,"receivedTime":"Aug 15, 2023 10:00:12 AM"},{"chunkId":11,"duration":29984,"receivedTime":"Aug 15, 2023 10:01:12 AM"},{"chunkId":12,"duration":30007,"receivedTime":"Aug 15, 2023 10:01:42 AM"}],"pendingAvailableKeyFrameInfo":[{"keyFrameId":5,"receivedTime":"Aug 15, 2023 10:00:42 AM","nextChunkId":11},{"keyFrameId":4,"receivedTime":"Aug 15, 2023 9:59:42 AM","nextChunkId":9},{"keyFrameId":6,"receivedTime":"Aug 15, 2023 10:01:42 AM","nextChunkId":13}],"keyFrameTimeInterval":60000,"decodedEncryptionKey":"","startGameChunkId":2,"gameLength":0,"clientAddedLag":0,"clientBackFetchingEnabled":false,"clientBackFetchingFreq":1000,"interestScore":0,"featuredGame":false,"createTime":"","endGameChunkId":-1,"endGameKeyFrameId":-1}"#;
+
+    fn metadata(end_startup_chunk_id: u32, start_game_chunk_id: u32) -> GameMetaData {
+        let mut metadata: GameMetaData = serde_json::from_str(METADATA).unwrap();
+        metadata.end_startup_chunk_id = end_startup_chunk_id;
+        metadata.start_game_chunk_id = start_game_chunk_id;
+        metadata
+    }
+
+    #[test]
+    fn test_backfill_queue() {
+        let queue = backfill_queue(Some(&metadata(1, 2)), 1..6, 1..3);
+        assert_eq!(
+            queue,
+            vec![
+                MediaData::GameDataChunk(2),
+                MediaData::KeyFrame(1),
+                MediaData::GameDataChunk(3),
+                MediaData::GameDataChunk(4),
+                MediaData::KeyFrame(2),
+                MediaData::GameDataChunk(5),
+                MediaData::GameDataChunk(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backfill_queue_skips_missing_chunks() {
+        // Chunks between the end of the startup and the start of the game do not exist
+        let queue = backfill_queue(Some(&metadata(2, 5)), 1..7, 1..1);
+        assert_eq!(
+            queue,
+            vec![
+                MediaData::GameDataChunk(5),
+                MediaData::GameDataChunk(6),
+                MediaData::GameDataChunk(1),
+                MediaData::GameDataChunk(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backfill_queue_without_metadata() {
+        let queue = backfill_queue(None, 3..5, 2..3);
+        assert_eq!(
+            queue,
+            vec![
+                MediaData::GameDataChunk(3),
+                MediaData::KeyFrame(2),
+                MediaData::GameDataChunk(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backfill_queue_from_pending_info() {
+        let metadata: GameMetaData = serde_json::from_str(PENDING_METADATA).unwrap();
+        let queue = backfill_queue(Some(&metadata), 1..12, 1..6);
+        assert_eq!(
+            queue,
+            vec![
+                MediaData::KeyFrame(4),
+                MediaData::GameDataChunk(8),
+                MediaData::GameDataChunk(9),
+                MediaData::KeyFrame(5),
+                MediaData::GameDataChunk(10),
+                MediaData::GameDataChunk(11),
+                MediaData::GameDataChunk(1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backfill_refetches_incomplete_metadata() {
+        let mut server = Server::new_async().await;
+        let _metadata = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameMetaData/KR/6654667050/1/token",
+            )
+            .with_body(serde_json::to_string(&metadata(2, 5)).unwrap())
+            .create_async()
+            .await;
+        let mut chunks = Vec::new();
+        for chunk_id in 1..7 {
+            // Chunks between the end of the startup and the start of the game do not exist
+            let expected = if (3..5).contains(&chunk_id) { 0 } else { 1 };
+            let path = format!(
+                "/observer-mode/rest/consumer/getGameDataChunk/KR/6654667050/{}/token",
+                chunk_id
+            );
+            let chunk = server
+                .mock("GET", path.as_str())
+                .with_body(format!("chunk{}", chunk_id))
+                .expect(expected)
+                .create_async()
+                .await;
+            chunks.push(chunk);
+        }
+
+        let folder = tempfile::tempdir().unwrap();
+        let mut record = Record::new(
+            "2.0.0".to_string(),
+            SpectatorEndpoint::new(server.url(), "KR".to_string()),
+            "6654667050".to_string(),
+            String::new(),
+            Box::new(DiskStorage::new(folder.path().to_path_buf()).unwrap()),
+        );
+        // Fetched before the startup and game chunk ids were published
+        record.metadata = Some(metadata(0, 0));
+        let record = Arc::new(record);
+        let (_shutdown_sender, shutdown) = watch::channel(false);
+        process_previous_media_data(
+            SpectatorClient::builder().rate_limit(None).build().unwrap(),
+            record.clone(),
+            RecordingOptions::default(),
+            1..7,
+            1..1,
+            shutdown,
+        )
+        .await
+        .unwrap();
+
+        for chunk in chunks {
+            chunk.assert_async().await;
+        }
+        assert!(record.has_game_data_chunk(5));
+        assert!(!record.has_game_data_chunk(3));
+    }
+
+    #[tokio::test]
+    async fn test_backfill_stops_on_shutdown() {
+        let mut server = Server::new_async().await;
+        let (shutdown_sender, shutdown) = watch::channel(false);
+        let shutdown_sender = Arc::new(shutdown_sender);
+        // The shutdown is requested while the first chunk is being downloaded
+        let first_chunk = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameDataChunk/KR/6654667050/2/token",
+            )
+            .with_body_from_request(move |_| {
+                shutdown_sender.send_replace(true);
+                b"chunk2".to_vec()
+            })
+            .expect(1)
+            .create_async()
+            .await;
+        let next_chunk = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameDataChunk/KR/6654667050/3/token",
+            )
+            .expect(0)
+            .create_async()
+            .await;
+
+        let folder = tempfile::tempdir().unwrap();
+        let record = Arc::new(Record::new(
+            "2.0.0".to_string(),
+            SpectatorEndpoint::new(server.url(), "KR".to_string()),
+            "6654667050".to_string(),
+            String::new(),
+            Box::new(DiskStorage::new(folder.path().to_path_buf()).unwrap()),
+        ));
+        let queue = Arc::new(Mutex::new(VecDeque::from([
+            MediaData::GameDataChunk(2),
+            MediaData::GameDataChunk(3),
+        ])));
+        backfill_worker(
+            SpectatorClient::builder().rate_limit(None).build().unwrap(),
+            record.clone(),
+            queue.clone(),
+            shutdown,
+        )
+        .await
+        .unwrap();
+
+        first_chunk.assert_async().await;
+        next_chunk.assert_async().await;
+        assert!(record.has_game_data_chunk(2));
+        assert_eq!(queue.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let mut server = Server::new_async().await;
+        let _version = server
+            .mock("GET", "/observer-mode/rest/consumer/version")
+            .with_body("2.0.0")
+            .create_async()
+            .await;
+        let _metadata = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameMetaData/KR/6654667050/1/token",
+            )
+            .with_body(METADATA)
+            .create_async()
+            .await;
+        let last_chunk_info = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getLastChunkInfo/KR/6654667050/0/token",
+            )
+            .with_status(500)
+            .expect(3)
+            .create_async()
+            .await;
+
+        let folder = tempfile::tempdir().unwrap();
+        let storage = DiskStorage::new(folder.path().to_path_buf()).unwrap();
+        let (_shutdown_sender, shutdown) = watch::channel(false);
+        let result = new(
+            SpectatorClient::builder().rate_limit(None).build().unwrap(),
+            SpectatorEndpoint::new(server.url(), "KR".to_string()),
+            "6654667050".to_string(),
+            String::new(),
+            Box::new(storage),
+            Box::new(StorageSink),
+            RecordingOptions {
+                retry_delay: Duration::ZERO,
+                max_retries: Some(2),
+                ..RecordingOptions::default()
+            },
+            shutdown,
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::Status { .. })));
+        last_chunk_info.assert_async().await;
+
+        let storage = DiskStorage::new(folder.path().to_path_buf()).unwrap();
+        let json = String::from_utf8(storage.load_record("6654667050").unwrap()).unwrap();
+        let stored_record = StoredRecord::from_json(&json).unwrap();
+        assert_eq!(stored_record.status, RecordStatus::Failed);
+    }
+}
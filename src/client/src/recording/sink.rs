@@ -0,0 +1,130 @@
+use crate::error::Error;
+
+use super::models::Record;
+
+use lol_replay_db::repository;
+use lol_replay_db::DbConnection;
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// Where the record file is written once the recording stops
+pub trait RecordSink: Send + Sync {
+    fn save(&self, record: &Record) -> Result<(), Error>;
+}
+
+// Next to the media data, so a record folder is self-contained
+pub struct StorageSink;
+
+impl RecordSink for StorageSink {
+    fn save(&self, record: &Record) -> Result<(), Error> {
+        let json = serde_json::to_vec(record)?;
+        record
+            .storage
+            .store_record(&record.game_id, json)
+            .map_err(Error::Storage)
+    }
+}
+
+// {path}/{platform_id}/{game_id}.json
+pub struct DirectorySink {
+    path: PathBuf,
+}
+
+impl DirectorySink {
+    pub fn new(path: PathBuf) -> Self {
+        DirectorySink { path }
+    }
+}
+
+impl RecordSink for DirectorySink {
+    fn save(&self, record: &Record) -> Result<(), Error> {
+        let folder = self.path.join(&record.endpoint.platform_id);
+        fs::create_dir_all(&folder).map_err(Error::Storage)?;
+        let json = serde_json::to_vec(record)?;
+        fs::write(folder.join(format!("{}.json", record.game_id)), json).map_err(Error::Storage)
+    }
+}
+
+// One row per game, replaced when the same game is recorded again
+pub struct DatabaseSink {
+    conn: Mutex<DbConnection>,
+}
+
+impl DatabaseSink {
+    pub fn connect(database_url: &str) -> Result<Self, Error> {
+        let mut conn = lol_replay_db::establish_connection(database_url)
+            .map_err(|error| Error::Database(Box::new(error)))?;
+        lol_replay_db::run_migrations(&mut conn).map_err(Error::Database)?;
+        Ok(DatabaseSink {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl RecordSink for DatabaseSink {
+    fn save(&self, record: &Record) -> Result<(), Error> {
+        let mut conn = self.conn.lock().unwrap();
+        repository::upsert_stored_record(&mut conn, &record.to_stored_record())
+            .map_err(Error::Database)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::models::SpectatorEndpoint;
+    use crate::recording::storage::DiskStorage;
+
+    use lol_replay_common::record::StoredRecord;
+
+    fn record(folder: PathBuf) -> Record {
+        Record::new(
+            "2.0.0".to_string(),
+            SpectatorEndpoint::new("http://127.0.0.1".to_string(), "KR".to_string()),
+            "6654667050".to_string(),
+            "oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6".to_string(),
+            Box::new(DiskStorage::new(folder).unwrap()),
+        )
+    }
+
+    #[test]
+    fn test_storage_sink() {
+        let folder = tempfile::tempdir().unwrap();
+        let record = record(folder.path().to_path_buf());
+        StorageSink.save(&record).unwrap();
+
+        let json = record.storage.load_record("6654667050").unwrap();
+        let stored_record = StoredRecord::from_json(&String::from_utf8(json).unwrap()).unwrap();
+        assert_eq!(stored_record.game_id, "6654667050");
+    }
+
+    #[test]
+    fn test_directory_sink() {
+        let folder = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let record = record(folder.path().to_path_buf());
+        DirectorySink::new(output.path().to_path_buf())
+            .save(&record)
+            .unwrap();
+
+        assert!(output.path().join("KR/6654667050.json").is_file());
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    #[test]
+    fn test_database_sink() {
+        let folder = tempfile::tempdir().unwrap();
+        let record = record(folder.path().to_path_buf());
+        let sink = DatabaseSink::connect(":memory:").unwrap();
+        sink.save(&record).unwrap();
+        sink.save(&record).unwrap();
+
+        let mut conn = sink.conn.lock().unwrap();
+        let records = repository::list_records(&mut conn).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].status, "recording");
+    }
+}
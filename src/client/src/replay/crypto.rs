@@ -0,0 +1,136 @@
+use crate::recording::models::Record;
+
+use flate2::read::GzDecoder;
+use log::debug;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Read;
+
+pub use lol_replay_common::crypto::{chunk_key, decrypt, encrypt, encryption_key};
+
+pub struct DecryptedRecord {
+    pub game_data_chunks: BTreeMap<u32, Vec<u8>>,
+    pub keyframes: BTreeMap<u32, Vec<u8>>,
+}
+
+// Decrypt and decompress every chunk and keyframe of a record from its storage
+pub fn decrypt_record(record: &Record) -> Result<DecryptedRecord, io::Error> {
+    let key = chunk_key(&record.game_id, &record.encryption_key)?;
+
+    let chunk_ids = record.game_data_chunks.lock().unwrap().clone();
+    let mut game_data_chunks = BTreeMap::new();
+    for chunk_id in chunk_ids {
+        debug!("Decrypting game data chunk id {}", chunk_id);
+        let data = record.storage.load_game_data_chunk(chunk_id)?;
+        game_data_chunks.insert(chunk_id, decode_payload(&key, &data)?);
+    }
+
+    let keyframe_ids = record.keyframes.lock().unwrap().clone();
+    let mut keyframes = BTreeMap::new();
+    for keyframe_id in keyframe_ids {
+        debug!("Decrypting keyframe {}", keyframe_id);
+        let data = record.storage.load_key_frame(keyframe_id)?;
+        keyframes.insert(keyframe_id, decode_payload(&key, &data)?);
+    }
+
+    Ok(DecryptedRecord {
+        game_data_chunks,
+        keyframes,
+    })
+}
+
+// Chunks and keyframes are gzip compressed before being encrypted
+pub fn decode_payload(key: &[u8], data: &[u8]) -> Result<Vec<u8>, io::Error> {
+    decompress(&decrypt(key, data)?)
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::models::SpectatorEndpoint;
+    use crate::recording::storage::DiskStorage;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{ErrorKind, Write};
+    use std::path::PathBuf;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    const GAME_ID: &str = "6654667050";
+    const ENCRYPTION_KEY: &str = "oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6";
+
+    fn fixture_record() -> Record {
+        let storage =
+            DiskStorage::open(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/KR"));
+        let record = Record::new(
+            "2.0.0".to_string(),
+            SpectatorEndpoint::new("http://localhost".to_string(), "KR".to_string()),
+            GAME_ID.to_string(),
+            ENCRYPTION_KEY.to_string(),
+            Box::new(storage),
+        );
+        record.insert_game_data_chunk(1);
+        record.insert_game_data_chunk(2);
+        record.insert_keyframe(1);
+        record
+    }
+
+    #[test]
+    fn test_chunk_key() {
+        let key = chunk_key(GAME_ID, ENCRYPTION_KEY).unwrap();
+        assert_eq!(key, b"fixturechunkkey!");
+    }
+
+    #[test]
+    fn test_chunk_key_invalid_base64() {
+        let error = chunk_key(GAME_ID, "not base64!").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_decode_payload_round_trip() {
+        let key = b"some chunk key";
+        let data = encrypt(key, &compress(b"payload")).unwrap();
+        assert_eq!(decode_payload(key, &data).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_decrypt_invalid_length() {
+        let error = decrypt(b"some chunk key", &[0; 7]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_decrypt_wrong_key() {
+        let data = encrypt(b"some chunk key", b"payload").unwrap();
+        assert!(decode_payload(b"another chunk key", &data).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_record() {
+        let decrypted = decrypt_record(&fixture_record()).unwrap();
+
+        assert_eq!(
+            decrypted
+                .game_data_chunks
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(decrypted.game_data_chunks[&1], b"startup game data chunk 1");
+        assert_eq!(decrypted.game_data_chunks[&2], b"game data chunk 2");
+        assert_eq!(decrypted.keyframes[&1], b"keyframe 1");
+    }
+}
@@ -0,0 +1,216 @@
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use std::io;
+use std::io::{ErrorKind, Read};
+
+// Block header marker flags, a set flag means the field is stored in its short form
+const MARKER_RELATIVE_TIMESTAMP: u8 = 0x80;
+const MARKER_SAME_TYPE: u8 = 0x40;
+const MARKER_RELATIVE_PARAMS: u8 = 0x20;
+const MARKER_SHORT_LENGTH: u8 = 0x10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    // Game time in seconds
+    pub timestamp: f32,
+    pub packet_type: u16,
+    pub params: u32,
+    pub payload: Vec<u8>,
+}
+
+// Iterate over the packets of a decrypted and decompressed game data chunk.
+// Packet types are not interpreted, unknown ones are returned like any other.
+pub struct PacketReader<R: Read> {
+    reader: R,
+    timestamp: f32,
+    packet_type: Option<u16>,
+    params: u32,
+    done: bool,
+}
+
+impl<R: Read> PacketReader<R> {
+    pub fn new(reader: R) -> Self {
+        PacketReader {
+            reader,
+            timestamp: 0.0,
+            packet_type: None,
+            params: 0,
+            done: false,
+        }
+    }
+
+    fn read_packet(&mut self, marker: u8) -> Result<Packet, io::Error> {
+        self.timestamp = if marker & MARKER_RELATIVE_TIMESTAMP != 0 {
+            self.timestamp + self.reader.read_u8()? as f32 / 1000.0
+        } else {
+            self.reader.read_f32::<LittleEndian>()?
+        };
+
+        let length = if marker & MARKER_SHORT_LENGTH != 0 {
+            self.reader.read_u8()? as u32
+        } else {
+            self.reader.read_u32::<LittleEndian>()?
+        };
+
+        let packet_type = if marker & MARKER_SAME_TYPE != 0 {
+            self.packet_type.ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    "packet reuses the previous type but is the first of the stream",
+                )
+            })?
+        } else {
+            self.reader.read_u16::<LittleEndian>()?
+        };
+        self.packet_type = Some(packet_type);
+
+        self.params = if marker & MARKER_RELATIVE_PARAMS != 0 {
+            self.params.wrapping_add(self.reader.read_u8()? as u32)
+        } else {
+            self.reader.read_u32::<LittleEndian>()?
+        };
+
+        // The length is not trusted, the payload only grows as far as the stream goes
+        let mut payload = Vec::new();
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut payload)?;
+        if payload.len() < length as usize {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "packet payload is shorter than its length",
+            ));
+        }
+
+        Ok(Packet {
+            timestamp: self.timestamp,
+            packet_type,
+            params: self.params,
+            payload,
+        })
+    }
+}
+
+impl<R: Read> Iterator for PacketReader<R> {
+    type Item = Result<Packet, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        // A clean end of stream can only happen between two blocks
+        let mut marker = [0; 1];
+        let result = match self.reader.read(&mut marker) {
+            Ok(0) => {
+                self.done = true;
+                return None;
+            }
+            Ok(_) => self.read_packet(marker[0]),
+            Err(e) => Err(e),
+        };
+
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    fn full_block(timestamp: f32, packet_type: u16, params: u32, payload: &[u8]) -> Vec<u8> {
+        let mut block = vec![0];
+        block.write_f32::<LittleEndian>(timestamp).unwrap();
+        block
+            .write_u32::<LittleEndian>(payload.len() as u32)
+            .unwrap();
+        block.write_u16::<LittleEndian>(packet_type).unwrap();
+        block.write_u32::<LittleEndian>(params).unwrap();
+        block.extend_from_slice(payload);
+        block
+    }
+
+    #[test]
+    fn test_read_full_blocks() {
+        let mut data = full_block(1.5, 0x25, 0x4000_0001, b"abc");
+        data.extend(full_block(2.0, 0xffff, 7, b""));
+
+        let packets = PacketReader::new(data.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            packets,
+            vec![
+                Packet {
+                    timestamp: 1.5,
+                    packet_type: 0x25,
+                    params: 0x4000_0001,
+                    payload: b"abc".to_vec(),
+                },
+                Packet {
+                    timestamp: 2.0,
+                    packet_type: 0xffff,
+                    params: 7,
+                    payload: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_short_blocks() {
+        let mut data = full_block(1.0, 0x25, 100, b"abc");
+        // Relative timestamp, short length, same type and relative params
+        data.extend([0xf0, 250, 2, 5]);
+        data.extend(b"de");
+
+        let packets = PacketReader::new(data.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[1].timestamp, 1.25);
+        assert_eq!(packets[1].packet_type, 0x25);
+        assert_eq!(packets[1].params, 105);
+        assert_eq!(packets[1].payload, b"de");
+    }
+
+    #[test]
+    fn test_read_empty_stream() {
+        assert_eq!(PacketReader::new(&[][..]).count(), 0);
+    }
+
+    #[test]
+    fn test_read_truncated_payload() {
+        let mut data = full_block(1.0, 0x25, 100, b"abc");
+        data.truncate(data.len() - 1);
+
+        let mut reader = PacketReader::new(data.as_slice());
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_read_huge_length() {
+        let mut data = full_block(1.0, 0x25, 100, b"abc");
+        data[5..9].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let mut reader = PacketReader::new(data.as_slice());
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_read_same_type_first_packet() {
+        let data = [0xf0, 0, 0, 0];
+        let error = PacketReader::new(&data[..]).next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::api::models::{GameKey, GameMetaData, SpectatorEndpoint};
+use crate::recording::models::{Record, RecordStatus};
+use crate::recording::storage::Storage;
+
+use log::debug;
+
+use std::io;
+use std::io::{Read, Seek, Write};
+
+pub use lol_replay_common::rofl::{
+    read_rofl, write_rofl, PayloadEntry, PayloadHeader, Rofl, RoflMetadata, ENTRY_HEADER_LENGTH,
+    ENTRY_TYPE_CHUNK, ENTRY_TYPE_KEYFRAME, HEADER_LENGTH, MAGIC, PAYLOAD_HEADER_LENGTH,
+    SIGNATURE_LENGTH,
+};
+
+// Write a record and the chunks and keyframes of its storage as a .rofl replay file
+pub fn export<W: Write>(record: &Record, writer: W) -> Result<(), io::Error> {
+    lol_replay_common::rofl::export(&record.to_stored_record(), record.storage.as_ref(), writer)
+}
+
+// Store the chunks and keyframes of a .rofl replay file and build the matching record, the
+// storage is opened for the game id read from the file
+pub fn import<R, F>(
+    reader: &mut R,
+    platform_id: String,
+    open_storage: F,
+) -> Result<Record, io::Error>
+where
+    R: Read + Seek,
+    F: FnOnce(&str) -> Result<Box<dyn Storage>, io::Error>,
+{
+    let rofl = read_rofl(reader)?;
+    let header = &rofl.payload_header;
+    let storage = open_storage(&header.game_id.to_string())?;
+
+    // Replay files do not tell which spectator API version the game was recorded from
+    let mut record = Record::new(
+        String::new(),
+        SpectatorEndpoint::new(String::new(), platform_id.clone()),
+        header.game_id.to_string(),
+        header.encryption_key.clone(),
+        storage,
+    );
+    if !rofl.metadata.game_version.is_empty() {
+        record.game_version = Some(rofl.metadata.game_version.clone());
+    }
+
+    for entry in rofl.entries {
+        match entry.entry_type {
+            ENTRY_TYPE_CHUNK => {
+                debug!("Storing game data chunk id {}", entry.id);
+                record.storage.store_game_data_chunk(entry.id, entry.data)?;
+                record.insert_game_data_chunk(entry.id);
+            }
+            ENTRY_TYPE_KEYFRAME => {
+                debug!("Storing keyframe {}", entry.id);
+                record.storage.store_key_frame(entry.id, entry.data)?;
+                record.insert_keyframe(entry.id);
+            }
+            entry_type => {
+                debug!("Skipping payload entry {} of type {}", entry.id, entry_type);
+            }
+        }
+    }
+
+    let last_chunk_id = record
+        .game_data_chunks
+        .lock()
+        .unwrap()
+        .iter()
+        .max()
+        .cloned()
+        .unwrap_or(rofl.metadata.last_game_chunk_id);
+    let last_key_frame_id = record
+        .keyframes
+        .lock()
+        .unwrap()
+        .iter()
+        .max()
+        .cloned()
+        .unwrap_or(rofl.metadata.last_key_frame_id);
+
+    record.metadata = Some(GameMetaData {
+        game_key: GameKey {
+            game_id: header.game_id,
+            platform_id,
+        },
+        game_server_address: String::new(),
+        port: 0,
+        encryption_key: header.encryption_key.clone(),
+        chunk_time_interval: 30000,
+        start_time: String::new(),
+        game_ended: true,
+        last_chunk_id,
+        last_key_frame_id,
+        end_startup_chunk_id: header.end_startup_chunk_id,
+        delay_time: 0,
+        pending_available_chunk_info: vec![],
+        pending_available_key_frame_info: vec![],
+        key_frame_time_interval: header.keyframe_interval as u64,
+        decoded_encryption_key: String::new(),
+        start_game_chunk_id: header.start_game_chunk_id,
+        game_length: header.game_length,
+        client_added_lag: 0,
+        client_back_fetching_enabled: false,
+        client_back_fetching_freq: 1000,
+        interest_score: 0,
+        featured_game: false,
+        create_time: String::new(),
+        end_game_chunk_id: last_chunk_id as i32,
+        end_game_key_frame_id: last_key_frame_id as i32,
+    });
+    record.set_status(RecordStatus::Complete);
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::storage::DiskStorage;
+    use byteorder::{LittleEndian, ReadBytesExt};
+    use std::io::Cursor;
+    use std::io::{ErrorKind, SeekFrom};
+    use std::path::PathBuf;
+
+    fn fixture_record() -> Record {
+        let storage =
+            DiskStorage::open(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/KR"));
+        let mut record = Record::new(
+            "2.0.0".to_string(),
+            SpectatorEndpoint::new("http://localhost".to_string(), "KR".to_string()),
+            "6654667050".to_string(),
+            "oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6".to_string(),
+            Box::new(storage),
+        );
+        record.metadata = Some(
+            serde_json::from_str(r#"{"gameKey":{"gameId":6654667050,"platformId":"KR"},"gameServerAddress":"","port":0,"encryptionKey":"","chunkTimeInterval":30000,"startTime":"Aug 15, 2023 8:01:42 PM","gameEnded":true,"lastChunkId":2,"lastKeyFrameId":1,"endStartupChunkId":1,"delayTime":180000,"pendingAvailableChunkInfo":[],"pendingAvailableKeyFrameInfo":[],"keyFrameTimeInterval":60000,"decodedEncryptionKey":"","startGameChunkId":2,"gameLength":0,"clientAddedLag":0,"clientBackFetchingEnabled":false,"clientBackFetchingFreq":1000,"interestScore":3325,"featuredGame":false,"createTime":"Aug 15, 2023 8:01:55 PM","endGameChunkId":2,"endGameKeyFrameId":1}"#)
+                .unwrap(),
+        );
+        record.insert_game_data_chunk(1);
+        record.insert_game_data_chunk(2);
+        record.insert_keyframe(1);
+        record
+    }
+
+    #[test]
+    fn test_export_without_metadata() {
+        let mut record = fixture_record();
+        record.metadata = None;
+
+        let error = export(&record, Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_export() {
+        let record = fixture_record();
+        let mut rofl = Vec::new();
+        export(&record, &mut rofl).unwrap();
+
+        let mut cursor = Cursor::new(&rofl);
+        let mut magic = [0; 6];
+        cursor.read_exact(&mut magic).unwrap();
+        assert_eq!(&magic, MAGIC);
+
+        cursor.seek(SeekFrom::Start(262)).unwrap();
+        assert_eq!(cursor.read_u16::<LittleEndian>().unwrap(), HEADER_LENGTH);
+        assert_eq!(
+            cursor.read_u32::<LittleEndian>().unwrap(),
+            rofl.len() as u32
+        );
+        let metadata_offset = cursor.read_u32::<LittleEndian>().unwrap() as usize;
+        let metadata_length = cursor.read_u32::<LittleEndian>().unwrap() as usize;
+        let payload_header_offset = cursor.read_u32::<LittleEndian>().unwrap();
+
+        let metadata: RoflMetadata =
+            serde_json::from_slice(&rofl[metadata_offset..metadata_offset + metadata_length])
+                .unwrap();
+        assert_eq!(metadata.game_length, 60000);
+        assert_eq!(metadata.last_game_chunk_id, 2);
+        assert_eq!(metadata.last_key_frame_id, 1);
+        // The spectator API version is not the game version
+        assert_eq!(metadata.game_version, "");
+
+        cursor
+            .seek(SeekFrom::Start(payload_header_offset as u64))
+            .unwrap();
+        assert_eq!(cursor.read_u64::<LittleEndian>().unwrap(), 6654667050);
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 60000);
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 1);
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 2);
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 1);
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 2);
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 60000);
+        let key_length = cursor.read_u16::<LittleEndian>().unwrap() as usize;
+        let mut key = vec![0; key_length];
+        cursor.read_exact(&mut key).unwrap();
+        assert_eq!(key, record.encryption_key.as_bytes());
+
+        // Second chunk entry then the keyframe entry
+        cursor
+            .seek(SeekFrom::Current(ENTRY_HEADER_LENGTH as i64))
+            .unwrap();
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 2);
+        assert_eq!(cursor.read_u8().unwrap(), ENTRY_TYPE_CHUNK);
+        let chunk_length = cursor.read_u32::<LittleEndian>().unwrap();
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 0);
+        let chunk_offset = cursor.read_u32::<LittleEndian>().unwrap();
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 1);
+        assert_eq!(cursor.read_u8().unwrap(), ENTRY_TYPE_KEYFRAME);
+        cursor.read_u32::<LittleEndian>().unwrap();
+        assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 3);
+
+        cursor.seek(SeekFrom::Current(4)).unwrap();
+        let data_start = cursor.position() as usize + chunk_offset as usize;
+        assert_eq!(
+            rofl[data_start..data_start + chunk_length as usize],
+            record.storage.load_game_data_chunk(2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_export_import_round_trip() {
+        let mut record = fixture_record();
+        record.game_version = Some("13.16.526.1356".to_string());
+        let mut rofl = Vec::new();
+        export(&record, &mut rofl).unwrap();
+
+        let folder = tempfile::tempdir().unwrap();
+        let storage = DiskStorage::new(folder.path().to_path_buf()).unwrap();
+        let imported = import(&mut Cursor::new(rofl), "KR".to_string(), |_| {
+            Ok(Box::new(storage))
+        })
+        .unwrap();
+
+        assert_eq!(imported.game_id, "6654667050");
+        assert_eq!(imported.encryption_key, record.encryption_key);
+        assert_eq!(imported.version, "");
+        assert_eq!(imported.game_version.as_deref(), Some("13.16.526.1356"));
+        assert!(imported.has_game_data_chunk(1));
+        assert!(imported.has_game_data_chunk(2));
+        assert!(imported.has_keyframe(1));
+        assert_eq!(
+            imported.storage.load_key_frame(1).unwrap(),
+            record.storage.load_key_frame(1).unwrap()
+        );
+
+        let metadata = imported.metadata.unwrap();
+        assert_eq!(metadata.game_key.game_id, 6654667050);
+        assert_eq!(metadata.game_key.platform_id, "KR");
+        assert_eq!(metadata.start_game_chunk_id, 2);
+        assert_eq!(metadata.end_startup_chunk_id, 1);
+        assert_eq!(metadata.last_chunk_id, 2);
+        assert_eq!(metadata.end_game_key_frame_id, 1);
+        assert_eq!(metadata.game_length, 60000);
+        assert!(metadata.game_ended);
+    }
+}
@@ -0,0 +1,407 @@
+use serde::Deserialize;
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// LOL_REPLAY_<SECTION>_<KEY> overrides <key> of [<section>], e.g. LOL_REPLAY_SERVER_BIND
+pub const ENV_PREFIX: &str = "LOL_REPLAY_";
+
+// How the value of an environment override is read, from the type of the key it sets
+#[derive(Clone, Copy)]
+enum EnvValue {
+    String,
+    Integer,
+    // One address or a TOML array of them
+    Addresses,
+    // A TOML array, or a list of one
+    Strings,
+}
+
+// Keys that can be overridden, other LOL_REPLAY_ variables are left to their owners
+const ENV_KEYS: [(&str, &str, EnvValue); 24] = [
+    ("spectator", "region", EnvValue::String),
+    ("spectator", "base_url", EnvValue::String),
+    ("spectator", "platform_id", EnvValue::String),
+    ("storage", "record_folder", EnvValue::String),
+    ("storage", "records_dir", EnvValue::String),
+    ("database", "url", EnvValue::String),
+    ("retry", "delay", EnvValue::Integer),
+    ("retry", "max_retries", EnvValue::Integer),
+    ("concurrency", "backfill", EnvValue::Integer),
+    ("concurrency", "requests_per_second", EnvValue::Integer),
+    ("concurrency", "max_in_flight", EnvValue::Integer),
+    ("http", "timeout", EnvValue::Integer),
+    ("http", "connect_timeout", EnvValue::Integer),
+    ("http", "pool_idle_timeout", EnvValue::Integer),
+    ("http", "pool_max_idle_per_host", EnvValue::Integer),
+    ("recording", "shutdown_timeout", EnvValue::Integer),
+    ("server", "bind", EnvValue::Addresses),
+    ("server", "tls_cert", EnvValue::String),
+    ("server", "tls_key", EnvValue::String),
+    ("server", "http_bind", EnvValue::Addresses),
+    ("server", "public_observer_address", EnvValue::String),
+    ("server", "workers", EnvValue::Integer),
+    ("server", "api_keys", EnvValue::Strings),
+    ("server", "relay_url", EnvValue::String),
+];
+
+// Settings shared by the client and the server, unset values fall back to command line flags
+// and then to the defaults of each binary
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub spectator: SpectatorConfig,
+    pub storage: StorageConfig,
+    pub database: DatabaseConfig,
+    pub retry: RetryConfig,
+    pub concurrency: ConcurrencyConfig,
+    pub http: HttpConfig,
+    pub recording: RecordingConfig,
+    pub server: ServerConfig,
+}
+
+// Either an official region or a custom base_url and platform_id
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpectatorConfig {
+    pub region: Option<String>,
+    pub base_url: Option<String>,
+    pub platform_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    // Folder receiving the chunks and keyframes, one sub folder per platform
+    pub record_folder: Option<PathBuf>,
+    // Folder receiving the record files instead of the record folder
+    pub records_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    // Seconds between two attempts to fetch the last chunk info
+    pub delay: Option<u64>,
+    // Consecutive failed attempts before the recording is given up, unlimited if unset
+    pub max_retries: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    pub backfill: Option<usize>,
+    pub requests_per_second: Option<u32>,
+    pub max_in_flight: Option<usize>,
+}
+
+// Spectator requests of the client, in seconds
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub timeout: Option<u64>,
+    pub connect_timeout: Option<u64>,
+    // Seconds an idle pooled connection is kept alive
+    pub pool_idle_timeout: Option<u64>,
+    pub pool_max_idle_per_host: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordingConfig {
+    // Seconds in-flight downloads may run after SIGINT or SIGTERM before the record is saved
+    pub shutdown_timeout: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    // One "host:port" address or a list of them
+    pub bind: Option<BindAddresses>,
+    // PEM files, the bind addresses only accept HTTPS when both are set
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    // Addresses serving the observer routes over plain HTTP, the spectator client of the game
+    // cannot use HTTPS
+    pub http_bind: Option<BindAddresses>,
+    // host:port launch commands send the game client to, the first http_bind address with TLS
+    // and the address of the request without
+    pub public_observer_address: Option<String>,
+    // Worker threads serving every bind address, one per CPU core if unset
+    pub workers: Option<usize>,
+    // Required by every route but the observer ones and /metrics, the server is open if unset
+    pub api_keys: Option<Vec<String>>,
+    // Spectator host whose games are relayed and recorded when they are not in the library
+    pub relay_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum BindAddresses {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl BindAddresses {
+    pub fn addresses(&self) -> Vec<&str> {
+        match self {
+            BindAddresses::One(address) => vec![address.as_str()],
+            BindAddresses::Many(addresses) => addresses.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "unable to read the config file: {}", error),
+            ConfigError::Parse(error) => write!(f, "invalid config: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(error) => Some(error),
+            ConfigError::Parse(error) => Some(error),
+        }
+    }
+}
+
+impl Config {
+    // Reads the config file if any, then applies the environment overrides
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let content = match path {
+            Some(path) => fs::read_to_string(path).map_err(ConfigError::Io)?,
+            None => String::new(),
+        };
+        Self::from_toml(&content, std::env::vars_os())
+    }
+
+    pub fn from_toml(
+        content: &str,
+        vars: impl IntoIterator<Item = (OsString, OsString)>,
+    ) -> Result<Self, ConfigError> {
+        let mut table: toml::Table = content.parse().map_err(ConfigError::Parse)?;
+        // Variables that are not valid UTF-8 are no overrides, std::env::vars() would panic on them
+        for (name, value) in vars {
+            if let (Some(name), Some(value)) = (name.to_str(), value.to_str()) {
+                apply_env_override(&mut table, name, value);
+            }
+        }
+        Config::deserialize(table).map_err(ConfigError::Parse)
+    }
+}
+
+fn apply_env_override(table: &mut toml::Table, name: &str, value: &str) {
+    let Some(name) = name.strip_prefix(ENV_PREFIX) else {
+        return;
+    };
+    let name = name.to_lowercase();
+    let Some((section, key, kind)) = ENV_KEYS
+        .iter()
+        .find(|(section, key, _)| name == format!("{}_{}", section, key))
+    else {
+        return;
+    };
+
+    if let toml::Value::Table(section) = table
+        .entry(*section)
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+    {
+        section.insert(key.to_string(), parse_env_value(value, *kind));
+    }
+}
+
+// Anything that does not read as the type of the key is kept as a string, for the error to name
+// the key
+fn parse_env_value(value: &str, kind: EnvValue) -> toml::Value {
+    let string = || toml::Value::String(value.to_string());
+    let parsed = || {
+        format!("value = {}", value)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+    };
+    match kind {
+        EnvValue::String => string(),
+        EnvValue::Integer => parsed()
+            .filter(toml::Value::is_integer)
+            .unwrap_or_else(string),
+        EnvValue::Addresses => parsed()
+            .filter(toml::Value::is_array)
+            .unwrap_or_else(string),
+        EnvValue::Strings => parsed()
+            .filter(toml::Value::is_array)
+            .unwrap_or_else(|| toml::Value::Array(vec![string()])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(OsString, OsString)> {
+        vars.iter()
+            .map(|(name, value)| (OsString::from(name), OsString::from(value)))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(
+            r#"
+            [spectator]
+            region = "KR"
+
+            [storage]
+            record_folder = "records"
+
+            [retry]
+            delay = 5
+            max_retries = 3
+
+            [server]
+            bind = "0.0.0.0:8443"
+            http_bind = ["0.0.0.0:8080", "[::]:8080"]
+            "#,
+            vars(&[]),
+        )
+        .unwrap();
+
+        assert_eq!(config.spectator.region.as_deref(), Some("KR"));
+        assert_eq!(config.storage.record_folder, Some(PathBuf::from("records")));
+        assert_eq!(config.retry.delay, Some(5));
+        assert_eq!(config.retry.max_retries, Some(3));
+        assert_eq!(
+            config.server.bind,
+            Some(BindAddresses::One("0.0.0.0:8443".to_string()))
+        );
+        assert_eq!(
+            config
+                .server
+                .http_bind
+                .as_ref()
+                .map(BindAddresses::addresses),
+            Some(vec!["0.0.0.0:8080", "[::]:8080"])
+        );
+        assert!(config.database.url.is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_from_toml_skips_non_utf8_variables() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let mut vars = vars(&[("LOL_REPLAY_RETRY_DELAY", "5")]);
+        vars.push((
+            OsString::from("LOL_REPLAY_SERVER_BIND"),
+            OsString::from_vec(b"\xff".to_vec()),
+        ));
+        vars.push((
+            OsString::from_vec(b"LOL_REPLAY_\xff".to_vec()),
+            OsString::from("ignored"),
+        ));
+        let config = Config::from_toml("", vars).unwrap();
+        assert_eq!(config.retry.delay, Some(5));
+        assert!(config.server.bind.is_none());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let config = Config::from_toml(
+            "[concurrency]\nbackfill = 2\n",
+            vars(&[
+                ("LOL_REPLAY_CONCURRENCY_BACKFILL", "8"),
+                ("LOL_REPLAY_DATABASE_URL", "postgres://localhost/lol_replay"),
+                (
+                    "LOL_REPLAY_SERVER_BIND",
+                    r#"["127.0.0.1:9090", "[::1]:9090"]"#,
+                ),
+                ("LOL_REPLAY_SERVER_WORKERS", "2"),
+                ("LOL_REPLAY_HTTP_POOL_MAX_IDLE_PER_HOST", "4"),
+                ("LOL_REPLAY_SERVER_API_KEYS", r#"["first", "second"]"#),
+                ("LOL_REPLAY_CONFIG", "ignored.toml"),
+                ("HOME", "/root"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.concurrency.backfill, Some(8));
+        assert_eq!(
+            config.database.url.as_deref(),
+            Some("postgres://localhost/lol_replay")
+        );
+        assert_eq!(
+            config.server.bind.as_ref().map(BindAddresses::addresses),
+            Some(vec!["127.0.0.1:9090", "[::1]:9090"])
+        );
+        assert_eq!(config.server.workers, Some(2));
+        assert_eq!(config.http.pool_max_idle_per_host, Some(4));
+        assert_eq!(
+            config.server.api_keys,
+            Some(vec!["first".to_string(), "second".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_env_overrides_follow_the_type_of_the_key() {
+        let config = Config::from_toml(
+            "",
+            vars(&[
+                ("LOL_REPLAY_DATABASE_URL", "1"),
+                ("LOL_REPLAY_SPECTATOR_PLATFORM_ID", "true"),
+                ("LOL_REPLAY_SERVER_API_KEYS", "123"),
+                ("LOL_REPLAY_SERVER_HTTP_BIND", "0.0.0.0:8080"),
+                // Not settings of the config file
+                ("LOL_REPLAY_SERVER_PORT", "8080"),
+                ("LOL_REPLAY_STORAGE_BACKEND", "disk"),
+                ("LOL_REPLAY_LOG", "debug"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.database.url.as_deref(), Some("1"));
+        assert_eq!(config.spectator.platform_id.as_deref(), Some("true"));
+        assert_eq!(config.server.api_keys, Some(vec!["123".to_string()]));
+        assert_eq!(
+            config.server.http_bind,
+            Some(BindAddresses::One("0.0.0.0:8080".to_string()))
+        );
+
+        let error =
+            Config::from_toml("", vars(&[("LOL_REPLAY_SERVER_WORKERS", "many")])).unwrap_err();
+        assert!(error.to_string().contains("workers"));
+    }
+
+    #[test]
+    fn test_example_config() {
+        let example = include_str!("../../../lol-replay.example.toml");
+        let config = Config::from_toml(example, vars(&[])).unwrap();
+        assert_eq!(config.spectator.region.as_deref(), Some("kr"));
+        assert_eq!(config.concurrency.backfill, Some(4));
+        assert_eq!(config.http.timeout, Some(30));
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let error = Config::from_toml("[server]\nport = 8080\n", vars(&[])).unwrap_err();
+        assert!(error.to_string().contains("unknown field"));
+    }
+}
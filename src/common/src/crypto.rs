@@ -0,0 +1,97 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blowfish::cipher::generic_array::GenericArray;
+use blowfish::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use blowfish::Blowfish;
+
+use std::io;
+use std::io::ErrorKind;
+
+const BLOCK_SIZE: usize = 8;
+
+// The game encryption key is base64 and itself encrypted using the game id as key
+pub fn chunk_key(game_id: &str, encryption_key: &str) -> Result<Vec<u8>, io::Error> {
+    let encrypted_key = BASE64
+        .decode(encryption_key)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    decrypt(game_id.as_bytes(), &encrypted_key)
+}
+
+// The encryption key a spectator client needs to get the chunk key back under this game id
+pub fn encryption_key(game_id: &str, chunk_key: &[u8]) -> Result<String, io::Error> {
+    Ok(BASE64.encode(encrypt(game_id.as_bytes(), chunk_key)?))
+}
+
+// Blowfish in ECB mode with PKCS#5 padding
+pub fn decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, io::Error> {
+    if data.is_empty() || !data.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "encrypted data length {} is not a multiple of {}",
+                data.len(),
+                BLOCK_SIZE
+            ),
+        ));
+    }
+
+    let cipher = cipher(key)?;
+    let mut decrypted = data.to_vec();
+    for block in decrypted.chunks_exact_mut(BLOCK_SIZE) {
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+    }
+
+    unpad(decrypted)
+}
+
+pub fn encrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let cipher = cipher(key)?;
+    let padding = BLOCK_SIZE - data.len() % BLOCK_SIZE;
+    let mut encrypted = data.to_vec();
+    encrypted.extend(std::iter::repeat_n(padding as u8, padding));
+    for block in encrypted.chunks_exact_mut(BLOCK_SIZE) {
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+    Ok(encrypted)
+}
+
+fn cipher(key: &[u8]) -> Result<Blowfish, io::Error> {
+    Blowfish::new_from_slice(key)
+        .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "invalid blowfish key length"))
+}
+
+fn unpad(mut data: Vec<u8>) -> Result<Vec<u8>, io::Error> {
+    let padding = data.last().copied().unwrap_or(0) as usize;
+    if padding == 0
+        || padding > BLOCK_SIZE
+        || padding > data.len()
+        || data[data.len() - padding..]
+            .iter()
+            .any(|&byte| byte as usize != padding)
+    {
+        return Err(io::Error::new(ErrorKind::InvalidData, "invalid padding"));
+    }
+    data.truncate(data.len() - padding);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME_ID: &str = "6654667050";
+    const ENCRYPTION_KEY: &str = "oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6";
+
+    #[test]
+    fn test_encryption_key_round_trip() {
+        let chunk_key = chunk_key(GAME_ID, ENCRYPTION_KEY).unwrap();
+        assert_eq!(encryption_key(GAME_ID, &chunk_key).unwrap(), ENCRYPTION_KEY);
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let data = encrypt(b"some chunk key", b"payload").unwrap();
+        assert_eq!(data.len() % BLOCK_SIZE, 0);
+        assert_eq!(decrypt(b"some chunk key", &data).unwrap(), b"payload");
+    }
+}
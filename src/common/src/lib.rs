@@ -0,0 +1,10 @@
+// Types shared by the client, the server and the database: the configuration, the spectator
+// wire models, the record and .rofl file formats and their encryption, the storage of their media
+// data and the rate limits of spectator hosts.
+pub mod config;
+pub mod crypto;
+pub mod models;
+pub mod rate_limit;
+pub mod record;
+pub mod rofl;
+pub mod storage;
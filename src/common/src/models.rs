@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SpectatorEndpoint {
+    pub base_url: String,
+    pub platform_id: String,
+}
+
+impl SpectatorEndpoint {
+    pub fn new(base_url: String, platform_id: String) -> Self {
+        SpectatorEndpoint {
+            base_url,
+            platform_id,
+        }
+    }
+}
+
+impl fmt::Display for SpectatorEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Base Url: {}", self.base_url)?;
+        writeln!(f, "Platform Id: {}", self.platform_id)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameMetaData {
+    pub game_key: GameKey,
+    pub game_server_address: String,
+    pub port: u32,
+    pub encryption_key: String,
+    pub chunk_time_interval: u32,
+    pub start_time: String,
+    pub game_ended: bool,
+    pub last_chunk_id: u32,
+    pub last_key_frame_id: u32,
+    pub end_startup_chunk_id: u32,
+    pub delay_time: u32,
+    pub pending_available_chunk_info: Vec<PendingAvailableChunkInfo>,
+    pub pending_available_key_frame_info: Vec<PendingAvailableKeyFrameInfo>,
+    pub key_frame_time_interval: u64,
+    pub decoded_encryption_key: String,
+    pub start_game_chunk_id: u32,
+    pub game_length: u32,
+    pub client_added_lag: u32,
+    pub client_back_fetching_enabled: bool,
+    pub client_back_fetching_freq: u32,
+    pub interest_score: u32,
+    pub featured_game: bool,
+    pub create_time: String,
+    pub end_game_chunk_id: i32,
+    pub end_game_key_frame_id: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameKey {
+    pub game_id: u64,
+    pub platform_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingAvailableChunkInfo {
+    pub chunk_id: u32,
+    pub duration: u32,
+    pub received_time: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingAvailableKeyFrameInfo {
+    pub key_frame_id: u32,
+    pub received_time: String,
+    pub next_chunk_id: u32,
+}
+
+impl GameMetaData {
+    // A keyframe is followed by the chunk starting at the same game time
+    pub fn keyframe_next_chunk_id(&self, keyframe_id: u32) -> u32 {
+        self.pending_available_key_frame_info
+            .iter()
+            .find(|info| info.key_frame_id == keyframe_id)
+            .map(|info| info.next_chunk_id)
+            .unwrap_or((self.start_game_chunk_id + 2 * keyframe_id).saturating_sub(1))
+    }
+
+    // Game length in milliseconds, estimated from the chunk count while the game is running
+    pub fn estimated_game_length(&self, chunk_count: usize) -> u32 {
+        if self.game_length > 0 {
+            self.game_length
+        } else {
+            self.chunk_time_interval * chunk_count as u32
+        }
+    }
+}
+
+impl fmt::Display for GameMetaData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Game Key: {:?}", self.game_key)?;
+        writeln!(f, "Server Address: {}", self.game_server_address)?;
+        writeln!(f, "Port: {}", self.port)?;
+        writeln!(f, "Encryption Key: {}", self.encryption_key)?;
+        writeln!(f, "Chunk Time Interval: {}", self.chunk_time_interval)?;
+        writeln!(f, "Start Time: {}", self.start_time)?;
+        writeln!(f, "Game Ended: {}", self.game_ended)?;
+        writeln!(f, "Last Chunk ID: {}", self.last_chunk_id)?;
+        writeln!(f, "Last Key Frame ID: {}", self.last_key_frame_id)?;
+        writeln!(f, "End Startup Chunk ID: {}", self.end_startup_chunk_id)?;
+        writeln!(f, "Delay Time: {}", self.delay_time)?;
+        writeln!(
+            f,
+            "Key Frame Time Interval: {}",
+            self.key_frame_time_interval
+        )?;
+        writeln!(f, "Decoded Encryption Key: {}", self.decoded_encryption_key)?;
+        writeln!(f, "Start Game Chunk ID: {}", self.start_game_chunk_id)?;
+        writeln!(f, "Game Length: {}", self.game_length)?;
+        writeln!(f, "Client Added Lag: {}", self.client_added_lag)?;
+        writeln!(
+            f,
+            "Client Back Fetching Enabled: {}",
+            self.client_back_fetching_enabled
+        )?;
+        writeln!(
+            f,
+            "Client Back Fetching Freq: {}",
+            self.client_back_fetching_freq
+        )?;
+        writeln!(f, "Interest Score: {}", self.interest_score)?;
+        writeln!(f, "Featured Game: {}", self.featured_game)?;
+        writeln!(f, "Create Time: {}", self.create_time)?;
+        writeln!(f, "End Game Chunk ID: {}", self.end_game_chunk_id)?;
+        writeln!(f, "End Game Key Frame ID: {}", self.end_game_key_frame_id)?;
+
+        writeln!(f, "Pending Available Chunk Info:")?;
+        for chunk_info in &self.pending_available_chunk_info {
+            writeln!(
+                f,
+                "\tChunk ID: {}, Duration: {}, Received Time: {}",
+                chunk_info.chunk_id, chunk_info.duration, chunk_info.received_time
+            )?;
+        }
+
+        writeln!(f, "Pending Available Key Frame Info:")?;
+        for key_frame_info in &self.pending_available_key_frame_info {
+            writeln!(
+                f,
+                "\tKey Frame ID: {}, Received Time: {}, Next Chunk ID: {}",
+                key_frame_info.key_frame_id,
+                key_frame_info.received_time,
+                key_frame_info.next_chunk_id
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkInfo {
+    pub chunk_id: u32,
+    pub available_since: u64,
+    pub next_available_chunk: u32,
+    pub key_frame_id: u32,
+    pub next_chunk_id: u32,
+    pub end_startup_chunk_id: u32,
+    pub start_game_chunk_id: u32,
+    pub end_game_chunk_id: u32,
+    pub duration: u32,
+}
+
+impl fmt::Display for ChunkInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chunk ID: {}", self.chunk_id)?;
+        writeln!(f, "Available Since: {}", self.available_since)?;
+        writeln!(f, "Next Available Chunk: {}", self.next_available_chunk)?;
+        writeln!(f, "Key Frame ID: {}", self.key_frame_id)?;
+        writeln!(f, "Next Chunk ID: {}", self.next_chunk_id)?;
+        writeln!(f, "End Startup Chunk ID: {}", self.end_startup_chunk_id)?;
+        writeln!(f, "Start Game Chunk ID: {}", self.start_game_chunk_id)?;
+        writeln!(f, "End Game Chunk ID: {}", self.end_game_chunk_id)?;
+        writeln!(f, "Duration: {}", self.duration)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{sleep_until, Duration, Instant};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests_per_second: u32,
+    pub max_in_flight: usize,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            requests_per_second: 10,
+            max_in_flight: 4,
+        }
+    }
+}
+
+// Rate limits are tracked per spectator host and shared by every clone of the limiter,
+// so recording several games from the same platform stays within one budget.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    hosts: Arc<Mutex<HashMap<String, Arc<HostLimiter>>>>,
+}
+
+#[derive(Debug)]
+struct HostLimiter {
+    in_flight: Arc<Semaphore>,
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    // Wait for a request slot on the host, the request is in flight until the permit is dropped
+    pub async fn acquire(&self, host: &str) -> OwnedSemaphorePermit {
+        let host_limiter = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(HostLimiter::new(self.limit)))
+            .clone();
+
+        let permit = host_limiter
+            .in_flight
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("rate limiter semaphore is never closed");
+
+        let slot = {
+            let mut next_slot = host_limiter.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + host_limiter.interval;
+            slot
+        };
+        sleep_until(slot).await;
+
+        permit
+    }
+}
+
+impl HostLimiter {
+    fn new(limit: RateLimit) -> Self {
+        HostLimiter {
+            in_flight: Arc::new(Semaphore::new(limit.max_in_flight.max(1))),
+            interval: Duration::from_secs(1) / limit.requests_per_second.max(1),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::timeout;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_per_second() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 10,
+            max_in_flight: 10,
+        });
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            drop(
+                limiter
+                    .acquire("spectator-consumer.kr.lol.pvp.net:80")
+                    .await,
+            );
+        }
+        assert_eq!(start.elapsed(), Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hosts_are_limited_separately() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 1,
+            max_in_flight: 1,
+        });
+
+        let start = Instant::now();
+        let _kr = limiter
+            .acquire("spectator-consumer.kr.lol.pvp.net:80")
+            .await;
+        let _euw = limiter
+            .acquire("spectator-consumer.euw1.lol.pvp.net:80")
+            .await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_in_flight() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 1000,
+            max_in_flight: 1,
+        });
+
+        let permit = limiter.acquire("localhost:80").await;
+        let cloned_limiter = limiter.clone();
+        assert!(timeout(
+            Duration::from_secs(5),
+            cloned_limiter.acquire("localhost:80")
+        )
+        .await
+        .is_err());
+
+        drop(permit);
+        assert!(timeout(
+            Duration::from_secs(5),
+            cloned_limiter.acquire("localhost:80")
+        )
+        .await
+        .is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::models::{GameMetaData, SpectatorEndpoint};
+use crate::storage::StorageDescriptor;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use std::path::PathBuf;
+
+// Version 1 files have no format_version, a debug string as storage and may lack a status
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordStatus {
+    Recording,
+    // The game ended and every announced chunk was requested
+    Complete,
+    // Recording was stopped before the end of the game
+    Interrupted,
+    Failed,
+}
+
+// What the client writes once a game is recorded, the media data itself stays in the storage
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StoredRecord {
+    pub format_version: u32,
+    // Version of the spectator API the game was recorded from
+    pub version: String,
+    // Version of the game itself, only known for imported replay files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_version: Option<String>,
+    pub endpoint: SpectatorEndpoint,
+    pub game_id: String,
+    pub encryption_key: String,
+    pub metadata: Option<GameMetaData>,
+    // Ids in ascending order
+    pub keyframes: Vec<u32>,
+    pub game_data_chunks: Vec<u32>,
+    pub storage: StorageDescriptor,
+    pub status: RecordStatus,
+}
+
+impl StoredRecord {
+    // Reads a record file of any format version, upgrading it to the current one
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let mut value: Value = serde_json::from_str(json)?;
+        let object = value
+            .as_object_mut()
+            .ok_or_else(|| serde_json::Error::custom("a record must be a JSON object"))?;
+
+        let format_version = match object.get("format_version") {
+            Some(format_version) => u32::deserialize(format_version)?,
+            None => 1,
+        };
+        if format_version > FORMAT_VERSION {
+            return Err(serde_json::Error::custom(format!(
+                "unsupported record format version {}, the latest known is {}",
+                format_version, FORMAT_VERSION
+            )));
+        }
+        if format_version < 2 {
+            upgrade_v1(object)?;
+        }
+        object.insert("format_version".to_string(), FORMAT_VERSION.into());
+
+        serde_json::from_value(value)
+    }
+}
+
+fn upgrade_v1(object: &mut Map<String, Value>) -> Result<(), serde_json::Error> {
+    let storage = match object.get("storage") {
+        Some(Value::String(storage)) => storage.clone(),
+        _ => return Err(serde_json::Error::custom("missing storage")),
+    };
+    let base_path = storage
+        .strip_prefix("DiskStorage: base_path: ")
+        .ok_or_else(|| serde_json::Error::custom(format!("unknown storage {}", storage)))?;
+    // The path was written with its Debug representation, a quoted and escaped string
+    let base_path: PathBuf = serde_json::from_str::<String>(base_path)?.into();
+    object.insert(
+        "storage".to_string(),
+        serde_json::to_value(StorageDescriptor::Disk { base_path })?,
+    );
+
+    // Before statuses were written records were only saved once the recording ended
+    object
+        .entry("status")
+        .or_insert_with(|| serde_json::to_value(RecordStatus::Complete).unwrap());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let json = r#"{"format_version":2,"version":"2.0.0","endpoint":{"base_url":"http://spectator.kr.lol.pvp.net:80","platform_id":"KR"},"game_id":"6654667050","encryption_key":"oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6","metadata":null,"keyframes":[1,2],"game_data_chunks":[1,2,3],"storage":{"type":"disk","base_path":"records/KR"},"status":"complete"}"#;
+
+        let record = StoredRecord::from_json(json).unwrap();
+        assert_eq!(record.endpoint.platform_id, "KR");
+        assert_eq!(record.game_data_chunks, vec![1, 2, 3]);
+        assert_eq!(record.status, RecordStatus::Complete);
+
+        assert_eq!(serde_json::to_string(&record).unwrap(), json);
+    }
+
+    #[test]
+    fn test_upgrade_v1() {
+        let json = r#"{"version":"2.0.0","endpoint":{"base_url":"http://spectator.kr.lol.pvp.net:80","platform_id":"KR"},"game_id":"6654667050","encryption_key":"oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6","metadata":null,"keyframes":[1],"game_data_chunks":[1,2],"storage":"DiskStorage: base_path: \"records/KR\""}"#;
+
+        let record = StoredRecord::from_json(json).unwrap();
+        assert_eq!(record.format_version, FORMAT_VERSION);
+        assert_eq!(
+            record.storage,
+            StorageDescriptor::Disk {
+                base_path: PathBuf::from("records/KR")
+            }
+        );
+        assert_eq!(record.status, RecordStatus::Complete);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let json = format!(r#"{{"format_version":{}}}"#, FORMAT_VERSION + 1);
+        let error = StoredRecord::from_json(&json).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("unsupported record format version"));
+    }
+
+    #[test]
+    fn test_unknown_v1_storage() {
+        let json = r#"{"storage":"S3Storage: bucket: \"records\""}"#;
+        let error = StoredRecord::from_json(json).unwrap_err();
+        assert!(error.to_string().contains("unknown storage"));
+    }
+}
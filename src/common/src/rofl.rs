@@ -0,0 +1,392 @@
+use crate::record::StoredRecord;
+use crate::storage::Storage;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+
+use std::io;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+pub const MAGIC: &[u8; 6] = b"RIOT\0\0";
+pub const SIGNATURE_LENGTH: usize = 256;
+// Magic, signature, then the header length and six u32 lengths and offsets
+pub const HEADER_LENGTH: u16 = 288;
+// Payload header without its trailing encryption key
+pub const PAYLOAD_HEADER_LENGTH: usize = 34;
+pub const ENTRY_HEADER_LENGTH: usize = 17;
+
+pub const ENTRY_TYPE_CHUNK: u8 = 1;
+pub const ENTRY_TYPE_KEYFRAME: u8 = 2;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoflMetadata {
+    pub game_length: u64,
+    pub game_version: String,
+    pub last_game_chunk_id: u32,
+    pub last_key_frame_id: u32,
+    pub stats_json: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PayloadHeader {
+    pub game_id: u64,
+    pub game_length: u32,
+    pub keyframe_count: u32,
+    pub chunk_count: u32,
+    pub end_startup_chunk_id: u32,
+    pub start_game_chunk_id: u32,
+    pub keyframe_interval: u32,
+    pub encryption_key: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PayloadEntry {
+    pub id: u32,
+    pub entry_type: u8,
+    pub next_chunk_id: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Rofl {
+    pub metadata: RoflMetadata,
+    pub payload_header: PayloadHeader,
+    pub entries: Vec<PayloadEntry>,
+}
+
+// Write a record and the chunks and keyframes of its storage as a .rofl replay file.
+// The signature can only be produced by Riot and is left empty.
+pub fn export<W: Write>(
+    record: &StoredRecord,
+    storage: &dyn Storage,
+    mut writer: W,
+) -> Result<(), io::Error> {
+    let metadata = record.metadata.as_ref().ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            "cannot export a record without game metadata",
+        )
+    })?;
+
+    let chunk_ids = &record.game_data_chunks;
+    let keyframe_ids = &record.keyframes;
+
+    let mut entries = Vec::with_capacity(chunk_ids.len() + keyframe_ids.len());
+    for chunk_id in chunk_ids {
+        entries.push(PayloadEntry {
+            id: *chunk_id,
+            entry_type: ENTRY_TYPE_CHUNK,
+            next_chunk_id: 0,
+            data: storage.load_game_data_chunk(*chunk_id)?,
+        });
+    }
+    for keyframe_id in keyframe_ids {
+        entries.push(PayloadEntry {
+            id: *keyframe_id,
+            entry_type: ENTRY_TYPE_KEYFRAME,
+            next_chunk_id: metadata.keyframe_next_chunk_id(*keyframe_id),
+            data: storage.load_key_frame(*keyframe_id)?,
+        });
+    }
+
+    let game_length = metadata.estimated_game_length(chunk_ids.len());
+    let rofl_metadata = RoflMetadata {
+        game_length: game_length as u64,
+        game_version: record.game_version.clone().unwrap_or_default(),
+        last_game_chunk_id: chunk_ids.last().cloned().unwrap_or(0),
+        last_key_frame_id: keyframe_ids.last().cloned().unwrap_or(0),
+        stats_json: "[]".to_string(),
+    };
+    let payload_header = PayloadHeader {
+        game_id: metadata.game_key.game_id,
+        game_length,
+        keyframe_count: keyframe_ids.len() as u32,
+        chunk_count: chunk_ids.len() as u32,
+        end_startup_chunk_id: metadata.end_startup_chunk_id,
+        start_game_chunk_id: metadata.start_game_chunk_id,
+        keyframe_interval: metadata.key_frame_time_interval as u32,
+        encryption_key: record.encryption_key.clone(),
+    };
+
+    write_rofl(&mut writer, &rofl_metadata, &payload_header, &entries)
+}
+
+pub fn write_rofl<W: Write>(
+    writer: &mut W,
+    metadata: &RoflMetadata,
+    payload_header: &PayloadHeader,
+    entries: &[PayloadEntry],
+) -> Result<(), io::Error> {
+    let metadata = serde_json::to_vec(metadata)?;
+
+    let metadata_offset = HEADER_LENGTH as u32;
+    let payload_header_offset = metadata_offset + metadata.len() as u32;
+    let payload_header_length =
+        (PAYLOAD_HEADER_LENGTH + payload_header.encryption_key.len()) as u32;
+    let payload_offset = payload_header_offset + payload_header_length;
+    // Summed as u64, offsets and lengths of a rofl file are u32 and large recordings overflow them
+    let payload_length = entries
+        .iter()
+        .map(|entry| (ENTRY_HEADER_LENGTH + entry.data.len()) as u64)
+        .sum::<u64>();
+    let file_length = u32::try_from(payload_offset as u64 + payload_length)
+        .map_err(|_| invalid_data("recording too large for a rofl file"))?;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[0; SIGNATURE_LENGTH])?;
+    writer.write_u16::<LittleEndian>(HEADER_LENGTH)?;
+    writer.write_u32::<LittleEndian>(file_length)?;
+    writer.write_u32::<LittleEndian>(metadata_offset)?;
+    writer.write_u32::<LittleEndian>(metadata.len() as u32)?;
+    writer.write_u32::<LittleEndian>(payload_header_offset)?;
+    writer.write_u32::<LittleEndian>(payload_header_length)?;
+    writer.write_u32::<LittleEndian>(payload_offset)?;
+
+    writer.write_all(&metadata)?;
+
+    writer.write_u64::<LittleEndian>(payload_header.game_id)?;
+    writer.write_u32::<LittleEndian>(payload_header.game_length)?;
+    writer.write_u32::<LittleEndian>(payload_header.keyframe_count)?;
+    writer.write_u32::<LittleEndian>(payload_header.chunk_count)?;
+    writer.write_u32::<LittleEndian>(payload_header.end_startup_chunk_id)?;
+    writer.write_u32::<LittleEndian>(payload_header.start_game_chunk_id)?;
+    writer.write_u32::<LittleEndian>(payload_header.keyframe_interval)?;
+    writer.write_u16::<LittleEndian>(payload_header.encryption_key.len() as u16)?;
+    writer.write_all(payload_header.encryption_key.as_bytes())?;
+
+    // Entry offsets are relative to the end of the entry headers
+    let mut offset = 0;
+    for entry in entries {
+        writer.write_u32::<LittleEndian>(entry.id)?;
+        writer.write_u8(entry.entry_type)?;
+        writer.write_u32::<LittleEndian>(entry.data.len() as u32)?;
+        writer.write_u32::<LittleEndian>(entry.next_chunk_id)?;
+        writer.write_u32::<LittleEndian>(offset)?;
+        offset += entry.data.len() as u32;
+    }
+    for entry in entries {
+        writer.write_all(&entry.data)?;
+    }
+
+    writer.flush()
+}
+
+// Read a .rofl replay file, its signature is not verified
+// Offsets and lengths are checked against the stream before anything is allocated
+pub fn read_rofl<R: Read + Seek>(reader: &mut R) -> Result<Rofl, io::Error> {
+    let stream_length = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut magic = [0; 6];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a rofl file"));
+    }
+
+    reader.seek(SeekFrom::Current(SIGNATURE_LENGTH as i64))?;
+    let _header_length = reader.read_u16::<LittleEndian>()?;
+    let _file_length = reader.read_u32::<LittleEndian>()?;
+    let metadata_offset = reader.read_u32::<LittleEndian>()?;
+    let metadata_length = reader.read_u32::<LittleEndian>()?;
+    let payload_header_offset = reader.read_u32::<LittleEndian>()?;
+    let _payload_header_length = reader.read_u32::<LittleEndian>()?;
+    let payload_offset = reader.read_u32::<LittleEndian>()?;
+
+    check_range(
+        stream_length,
+        metadata_offset,
+        metadata_length as u64,
+        "metadata",
+    )?;
+    reader.seek(SeekFrom::Start(metadata_offset as u64))?;
+    let mut metadata = vec![0; metadata_length as usize];
+    reader.read_exact(&mut metadata)?;
+    let metadata: RoflMetadata = serde_json::from_slice(&metadata)?;
+
+    reader.seek(SeekFrom::Start(payload_header_offset as u64))?;
+    let game_id = reader.read_u64::<LittleEndian>()?;
+    let game_length = reader.read_u32::<LittleEndian>()?;
+    let keyframe_count = reader.read_u32::<LittleEndian>()?;
+    let chunk_count = reader.read_u32::<LittleEndian>()?;
+    let end_startup_chunk_id = reader.read_u32::<LittleEndian>()?;
+    let start_game_chunk_id = reader.read_u32::<LittleEndian>()?;
+    let keyframe_interval = reader.read_u32::<LittleEndian>()?;
+    let encryption_key_length = reader.read_u16::<LittleEndian>()?;
+    check_range(
+        stream_length,
+        reader.stream_position()?,
+        encryption_key_length as u64,
+        "encryption key",
+    )?;
+    let mut encryption_key = vec![0; encryption_key_length as usize];
+    reader.read_exact(&mut encryption_key)?;
+    let payload_header = PayloadHeader {
+        game_id,
+        game_length,
+        keyframe_count,
+        chunk_count,
+        end_startup_chunk_id,
+        start_game_chunk_id,
+        keyframe_interval,
+        encryption_key: String::from_utf8(encryption_key)
+            .map_err(|_| invalid_data("encryption key is not valid utf-8"))?,
+    };
+
+    let entry_count = chunk_count
+        .checked_add(keyframe_count)
+        .ok_or_else(|| invalid_data("too many payload entries"))? as u64;
+    let headers_length = entry_count * ENTRY_HEADER_LENGTH as u64;
+    check_range(
+        stream_length,
+        payload_offset,
+        headers_length,
+        "payload entries",
+    )?;
+    let data_offset = payload_offset as u64 + headers_length;
+    reader.seek(SeekFrom::Start(payload_offset as u64))?;
+    let mut headers = Vec::with_capacity(entry_count as usize);
+    for _ in 0..entry_count {
+        let id = reader.read_u32::<LittleEndian>()?;
+        let entry_type = reader.read_u8()?;
+        let length = reader.read_u32::<LittleEndian>()?;
+        let next_chunk_id = reader.read_u32::<LittleEndian>()?;
+        let offset = reader.read_u32::<LittleEndian>()?;
+        headers.push((id, entry_type, length, next_chunk_id, offset));
+    }
+
+    let mut entries = Vec::with_capacity(headers.len());
+    for (id, entry_type, length, next_chunk_id, offset) in headers {
+        check_range(
+            stream_length,
+            data_offset + offset as u64,
+            length as u64,
+            "payload entry",
+        )?;
+        reader.seek(SeekFrom::Start(data_offset + offset as u64))?;
+        let mut data = vec![0; length as usize];
+        reader.read_exact(&mut data)?;
+        entries.push(PayloadEntry {
+            id,
+            entry_type,
+            next_chunk_id,
+            data,
+        });
+    }
+
+    Ok(Rofl {
+        metadata,
+        payload_header,
+        entries,
+    })
+}
+
+fn check_range(
+    stream_length: u64,
+    offset: impl Into<u64>,
+    length: u64,
+    what: &str,
+) -> Result<(), io::Error> {
+    match offset.into().checked_add(length) {
+        Some(end) if end <= stream_length => Ok(()),
+        _ => Err(invalid_data(&format!("{} out of the file", what))),
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_rofl_invalid_magic() {
+        let error = read_rofl(&mut Cursor::new(b"RIOS\0\0")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    // A file with one chunk, and where to patch it
+    fn rofl_file() -> Vec<u8> {
+        let metadata = RoflMetadata {
+            game_length: 30000,
+            game_version: String::new(),
+            last_game_chunk_id: 1,
+            last_key_frame_id: 0,
+            stats_json: "[]".to_string(),
+        };
+        let payload_header = PayloadHeader {
+            game_id: 6654667050,
+            game_length: 30000,
+            keyframe_count: 0,
+            chunk_count: 1,
+            end_startup_chunk_id: 1,
+            start_game_chunk_id: 1,
+            keyframe_interval: 60000,
+            encryption_key: "key".to_string(),
+        };
+        let entries = [PayloadEntry {
+            id: 1,
+            entry_type: ENTRY_TYPE_CHUNK,
+            next_chunk_id: 0,
+            data: b"chunk1".to_vec(),
+        }];
+        let mut rofl = Vec::new();
+        write_rofl(&mut rofl, &metadata, &payload_header, &entries).unwrap();
+        rofl
+    }
+
+    fn field_offset(rofl: &[u8], header_field: usize) -> usize {
+        let position = MAGIC.len() + SIGNATURE_LENGTH + 6 + header_field * 4;
+        u32::from_le_bytes(rofl[position..position + 4].try_into().unwrap()) as usize
+    }
+
+    fn patch_u32(rofl: &mut [u8], position: usize, value: u32) {
+        rofl[position..position + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn test_read_rofl() {
+        let rofl = read_rofl(&mut Cursor::new(rofl_file())).unwrap();
+        assert_eq!(rofl.payload_header.game_id, 6654667050);
+        assert_eq!(rofl.entries.len(), 1);
+        assert_eq!(rofl.entries[0].data, b"chunk1");
+    }
+
+    #[test]
+    fn test_read_rofl_metadata_out_of_the_file() {
+        let mut rofl = rofl_file();
+        let position = MAGIC.len() + SIGNATURE_LENGTH + 6 + 4;
+        patch_u32(&mut rofl, position, u32::MAX);
+
+        let error = read_rofl(&mut Cursor::new(rofl)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_read_rofl_too_many_entries() {
+        let mut rofl = rofl_file();
+        let payload_header_offset = field_offset(&rofl, 2);
+        patch_u32(&mut rofl, payload_header_offset + 12, u32::MAX);
+
+        let error = read_rofl(&mut Cursor::new(rofl)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let mut rofl = rofl_file();
+        patch_u32(&mut rofl, payload_header_offset + 16, 1_000_000);
+        let error = read_rofl(&mut Cursor::new(rofl)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_read_rofl_entry_out_of_the_file() {
+        let mut rofl = rofl_file();
+        let payload_offset = field_offset(&rofl, 4);
+        patch_u32(&mut rofl, payload_offset + 5, u32::MAX);
+
+        let error = read_rofl(&mut Cursor::new(rofl)).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+pub trait Storage: Send + Sync {
+    fn store_game_data_chunk(&self, chunk_id: u32, data: Vec<u8>) -> Result<(), io::Error>;
+    fn store_key_frame(&self, frame_id: u32, data: Vec<u8>) -> Result<(), io::Error>;
+
+    fn load_game_data_chunk(&self, chunk_id: u32) -> Result<Vec<u8>, io::Error>;
+    fn load_key_frame(&self, frame_id: u32) -> Result<Vec<u8>, io::Error>;
+
+    fn game_data_chunk_ids(&self) -> Result<Vec<u32>, io::Error>;
+    fn key_frame_ids(&self) -> Result<Vec<u32>, io::Error>;
+
+    // Record files kept next to the media data they describe
+    fn store_record(&self, game_id: &str, data: Vec<u8>) -> Result<(), io::Error>;
+    fn load_record(&self, game_id: &str) -> Result<Vec<u8>, io::Error>;
+
+    fn delete_game_data_chunk(&self, chunk_id: u32) -> Result<(), io::Error>;
+    fn delete_key_frame(&self, frame_id: u32) -> Result<(), io::Error>;
+    fn delete_record(&self, game_id: &str) -> Result<(), io::Error>;
+
+    fn descriptor(&self) -> StorageDescriptor;
+}
+
+// Where the media data of a record is stored, enough to open the storage again
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StorageDescriptor {
+    Disk { base_path: PathBuf },
+}
+
+impl StorageDescriptor {
+    pub fn open(&self) -> Result<Box<dyn Storage>, io::Error> {
+        match self {
+            StorageDescriptor::Disk { base_path } => {
+                Ok(Box::new(DiskStorage::new(base_path.clone())?))
+            }
+        }
+    }
+
+    // Same storage without creating its folders, for readers
+    pub fn open_read_only(&self) -> Result<Box<dyn Storage>, io::Error> {
+        match self {
+            StorageDescriptor::Disk { base_path } => {
+                Ok(Box::new(DiskStorage::open(base_path.clone())))
+            }
+        }
+    }
+}
+
+// {record_folder}/{platform_id}/{game_id}, each game keeps its own chunks and keyframes as their
+// ids restart at 1 for every game
+pub fn game_folder(record_folder: &Path, platform_id: &str, game_id: &str) -> PathBuf {
+    record_folder.join(platform_id).join(game_id)
+}
+
+pub struct DiskStorage {
+    base_path: PathBuf,
+}
+
+impl DiskStorage {
+    pub fn new(base_path: PathBuf) -> Result<Self, io::Error> {
+        Self::create_dir_if_not_exists(base_path.join("game_data_chunks"))?;
+        Self::create_dir_if_not_exists(base_path.join("keyframes"))?;
+        Self::create_dir_if_not_exists(base_path.join("records"))?;
+        Ok(DiskStorage { base_path })
+    }
+
+    // A missing folder fails on first access instead of being created
+    pub fn open(base_path: PathBuf) -> Self {
+        DiskStorage { base_path }
+    }
+
+    fn create_dir_if_not_exists(path: PathBuf) -> Result<(), io::Error> {
+        match fs::create_dir_all(&path) {
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(()), // If it already exists, just return Ok
+            other => other,
+        }
+    }
+
+    // Files are named after their id, anything else in the folder is ignored
+    fn list_ids(&self, folder: &str) -> Result<Vec<u32>, io::Error> {
+        let mut ids = fs::read_dir(self.base_path.join(folder))?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+            .collect::<Vec<_>>();
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+impl Storage for DiskStorage {
+    fn store_game_data_chunk(&self, chunk_id: u32, data: Vec<u8>) -> Result<(), io::Error> {
+        let path = self
+            .base_path
+            .join(format!("game_data_chunks/{}", chunk_id));
+        std::fs::write(path, data)
+    }
+
+    fn store_key_frame(&self, keyframe_id: u32, data: Vec<u8>) -> Result<(), io::Error> {
+        let path = self.base_path.join(format!("keyframes/{}", keyframe_id));
+        std::fs::write(path, data)
+    }
+
+    fn load_game_data_chunk(&self, chunk_id: u32) -> Result<Vec<u8>, io::Error> {
+        let path = self
+            .base_path
+            .join(format!("game_data_chunks/{}", chunk_id));
+        std::fs::read(path)
+    }
+
+    fn load_key_frame(&self, keyframe_id: u32) -> Result<Vec<u8>, io::Error> {
+        let path = self.base_path.join(format!("keyframes/{}", keyframe_id));
+        std::fs::read(path)
+    }
+
+    fn game_data_chunk_ids(&self) -> Result<Vec<u32>, io::Error> {
+        self.list_ids("game_data_chunks")
+    }
+
+    fn key_frame_ids(&self) -> Result<Vec<u32>, io::Error> {
+        self.list_ids("keyframes")
+    }
+
+    fn store_record(&self, game_id: &str, data: Vec<u8>) -> Result<(), io::Error> {
+        let path = self.base_path.join(format!("records/{}.json", game_id));
+        std::fs::write(path, data)
+    }
+
+    fn load_record(&self, game_id: &str) -> Result<Vec<u8>, io::Error> {
+        let path = self.base_path.join(format!("records/{}.json", game_id));
+        std::fs::read(path)
+    }
+
+    fn delete_game_data_chunk(&self, chunk_id: u32) -> Result<(), io::Error> {
+        let path = self
+            .base_path
+            .join(format!("game_data_chunks/{}", chunk_id));
+        std::fs::remove_file(path)
+    }
+
+    fn delete_key_frame(&self, keyframe_id: u32) -> Result<(), io::Error> {
+        let path = self.base_path.join(format!("keyframes/{}", keyframe_id));
+        std::fs::remove_file(path)
+    }
+
+    fn delete_record(&self, game_id: &str) -> Result<(), io::Error> {
+        let path = self.base_path.join(format!("records/{}.json", game_id));
+        std::fs::remove_file(path)
+    }
+
+    fn descriptor(&self) -> StorageDescriptor {
+        StorageDescriptor::Disk {
+            base_path: self.base_path.clone(),
+        }
+    }
+}
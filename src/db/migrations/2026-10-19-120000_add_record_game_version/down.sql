@@ -0,0 +1 @@
+ALTER TABLE records DROP COLUMN game_version;
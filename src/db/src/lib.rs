@@ -0,0 +1,31 @@
+pub mod models;
+pub mod repository;
+pub mod schema;
+
+use diesel::prelude::*;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+use std::error::Error;
+
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("lol-replay-db requires either the `sqlite` or the `postgres` feature");
+
+// The postgres backend takes precedence when both features are enabled
+#[cfg(feature = "postgres")]
+pub type DbConnection = diesel::pg::PgConnection;
+#[cfg(all(feature = "sqlite", not(feature = "postgres")))]
+pub type DbConnection = diesel::sqlite::SqliteConnection;
+
+#[cfg(feature = "postgres")]
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations_postgres");
+#[cfg(all(feature = "sqlite", not(feature = "postgres")))]
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+pub fn establish_connection(database_url: &str) -> ConnectionResult<DbConnection> {
+    DbConnection::establish(database_url)
+}
+
+pub fn run_migrations(conn: &mut DbConnection) -> Result<(), Box<dyn Error + Send + Sync>> {
+    conn.run_pending_migrations(MIGRATIONS)?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::schema::records;
+
+use diesel::prelude::*;
+use lol_replay_common::record::{StoredRecord, FORMAT_VERSION};
+use serde_json::Value;
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = records)]
+pub struct Record {
+    pub id: Option<i32>,
+    pub version: String,
+    pub endpoint: String,
+    pub base_url: String,
+    pub platform_id: String,
+    pub game_id: String,
+    pub encryption_key: String,
+    pub metadata: String,
+    pub keyframes: String,
+    pub game_data_chunks: String,
+    pub storage: String,
+    pub status: String,
+    pub game_version: Option<String>,
+}
+
+impl Record {
+    // Columns holding JSON are decoded back into the shared record format
+    pub fn to_stored_record(&self) -> serde_json::Result<StoredRecord> {
+        Ok(StoredRecord {
+            format_version: FORMAT_VERSION,
+            version: self.version.clone(),
+            game_version: self.game_version.clone(),
+            endpoint: serde_json::from_str(&self.endpoint)?,
+            game_id: self.game_id.clone(),
+            encryption_key: self.encryption_key.clone(),
+            metadata: serde_json::from_str(&self.metadata)?,
+            keyframes: serde_json::from_str(&self.keyframes)?,
+            game_data_chunks: serde_json::from_str(&self.game_data_chunks)?,
+            storage: serde_json::from_str(&self.storage)?,
+            status: serde_json::from_value(Value::String(self.status.clone()))?,
+        })
+    }
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = records, treat_none_as_null = true)]
+pub struct NewRecord<'a> {
+    pub version: &'a str,
+    pub endpoint: &'a str,
+    pub base_url: &'a str,
+    pub platform_id: &'a str,
+    pub game_id: &'a str,
+    pub encryption_key: &'a str,
+    pub metadata: &'a str,
+    pub keyframes: &'a str,
+    pub game_data_chunks: &'a str,
+    pub storage: &'a str,
+    pub status: &'a str,
+    pub game_version: Option<&'a str>,
+}
@@ -0,0 +1,186 @@
+use crate::models::{NewRecord, Record};
+use crate::schema::records;
+use crate::DbConnection;
+
+use diesel::prelude::*;
+use lol_replay_common::record::StoredRecord;
+
+use std::error::Error;
+
+pub fn insert_record(conn: &mut DbConnection, record: &NewRecord) -> QueryResult<usize> {
+    diesel::insert_into(records::table)
+        .values(record)
+        .execute(conn)
+}
+
+pub fn upsert_record(conn: &mut DbConnection, record: &NewRecord) -> QueryResult<usize> {
+    diesel::insert_into(records::table)
+        .values(record)
+        .on_conflict((records::platform_id, records::game_id))
+        .do_update()
+        .set(record)
+        .execute(conn)
+}
+
+// Stores the record as written by the client, replacing any earlier version of it
+pub fn upsert_stored_record(
+    conn: &mut DbConnection,
+    record: &StoredRecord,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let endpoint = serde_json::to_string(&record.endpoint)?;
+    let metadata = serde_json::to_string(&record.metadata)?;
+    let keyframes = serde_json::to_string(&record.keyframes)?;
+    let game_data_chunks = serde_json::to_string(&record.game_data_chunks)?;
+    let storage = serde_json::to_string(&record.storage)?;
+    let status = serde_json::to_value(record.status)?;
+
+    let new_record = NewRecord {
+        version: &record.version,
+        endpoint: &endpoint,
+        base_url: &record.endpoint.base_url,
+        platform_id: &record.endpoint.platform_id,
+        game_id: &record.game_id,
+        encryption_key: &record.encryption_key,
+        metadata: &metadata,
+        keyframes: &keyframes,
+        game_data_chunks: &game_data_chunks,
+        storage: &storage,
+        status: status.as_str().unwrap_or_default(),
+        game_version: record.game_version.as_deref(),
+    };
+    Ok(upsert_record(conn, &new_record)?)
+}
+
+pub fn find_record(
+    conn: &mut DbConnection,
+    platform_id: &str,
+    game_id: &str,
+) -> QueryResult<Option<Record>> {
+    records::table
+        .filter(records::platform_id.eq(platform_id))
+        .filter(records::game_id.eq(game_id))
+        .select(Record::as_select())
+        .first(conn)
+        .optional()
+}
+
+pub fn list_records(conn: &mut DbConnection) -> QueryResult<Vec<Record>> {
+    records::table
+        .order(records::id.asc())
+        .select(Record::as_select())
+        .load(conn)
+}
+
+pub fn delete_record(
+    conn: &mut DbConnection,
+    platform_id: &str,
+    game_id: &str,
+) -> QueryResult<usize> {
+    diesel::delete(
+        records::table
+            .filter(records::platform_id.eq(platform_id))
+            .filter(records::game_id.eq(game_id)),
+    )
+    .execute(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{establish_connection, run_migrations};
+
+    // Postgres tests run against DATABASE_URL inside a transaction that is never committed
+    #[cfg(feature = "postgres")]
+    fn test_connection() -> DbConnection {
+        let database_url = std::env::var("DATABASE_URL")
+            .unwrap_or_else(|_| "postgres://localhost/lol_replay_test".to_string());
+        let mut conn = establish_connection(&database_url).unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.begin_test_transaction().unwrap();
+        conn
+    }
+
+    #[cfg(all(feature = "sqlite", not(feature = "postgres")))]
+    fn test_connection() -> DbConnection {
+        let mut conn = establish_connection(":memory:").unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn
+    }
+
+    fn new_record<'a>(game_id: &'a str, metadata: &'a str) -> NewRecord<'a> {
+        NewRecord {
+            version: "2.0.0",
+            endpoint: "{}",
+            base_url: "http://spectator-consumer.kr.lol.pvp.net:80",
+            platform_id: "KR",
+            game_id,
+            encryption_key: "key",
+            metadata,
+            keyframes: "[1,2]",
+            game_data_chunks: "[1,2,3]",
+            storage: "{}",
+            status: "complete",
+            game_version: None,
+        }
+    }
+
+    #[test]
+    fn test_insert_and_find_record() {
+        let mut conn = test_connection();
+        insert_record(&mut conn, &new_record("6654667050", "{}")).unwrap();
+
+        let record = find_record(&mut conn, "KR", "6654667050").unwrap().unwrap();
+        assert!(record.id.is_some());
+        assert_eq!(record.game_data_chunks, "[1,2,3]");
+        assert!(find_record(&mut conn, "EUW1", "6654667050")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_insert_record_unique_game() {
+        let mut conn = test_connection();
+        insert_record(&mut conn, &new_record("6654667050", "{}")).unwrap();
+        assert!(insert_record(&mut conn, &new_record("6654667050", "{}")).is_err());
+    }
+
+    #[test]
+    fn test_upsert_record() {
+        let mut conn = test_connection();
+        upsert_record(&mut conn, &new_record("6654667050", "{}")).unwrap();
+        upsert_record(
+            &mut conn,
+            &new_record("6654667050", r#"{"gameEnded":true}"#),
+        )
+        .unwrap();
+
+        let records = list_records(&mut conn).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].metadata, r#"{"gameEnded":true}"#);
+    }
+
+    #[test]
+    fn test_upsert_stored_record() {
+        let mut conn = test_connection();
+        let json = r#"{"format_version":2,"version":"2.0.0","game_version":"13.16.526.1356","endpoint":{"base_url":"http://spectator-consumer.kr.lol.pvp.net:80","platform_id":"KR"},"game_id":"6654667050","encryption_key":"key","metadata":null,"keyframes":[1],"game_data_chunks":[1,2],"storage":{"type":"disk","base_path":"records/KR"},"status":"interrupted"}"#;
+        let stored_record = StoredRecord::from_json(json).unwrap();
+        upsert_stored_record(&mut conn, &stored_record).unwrap();
+
+        let record = find_record(&mut conn, "KR", "6654667050").unwrap().unwrap();
+        assert_eq!(record.status, "interrupted");
+        let loaded = record.to_stored_record().unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+    }
+
+    #[test]
+    fn test_delete_record() {
+        let mut conn = test_connection();
+        insert_record(&mut conn, &new_record("6654667050", "{}")).unwrap();
+        insert_record(&mut conn, &new_record("6654667051", "{}")).unwrap();
+
+        assert_eq!(delete_record(&mut conn, "KR", "6654667050").unwrap(), 1);
+        let records = list_records(&mut conn).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].game_id, "6654667051");
+    }
+}
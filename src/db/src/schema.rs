@@ -13,5 +13,7 @@ diesel::table! {
         keyframes -> Text,
         game_data_chunks -> Text,
         storage -> Text,
+        status -> Text,
+        game_version -> Nullable<Text>,
     }
 }
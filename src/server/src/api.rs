@@ -0,0 +1,339 @@
+use crate::error::Error;
+use crate::library::Library;
+use crate::relay::Relay;
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{delete, get, put, web, HttpResponse, Responder, Result};
+use lol_replay_common::models::{GameKey, GameMetaData};
+use lol_replay_common::record::{RecordStatus, StoredRecord};
+use lol_replay_common::rofl;
+use serde::{Deserialize, Serialize};
+
+// JSON API to browse and manage the recordings, mounted under /api
+pub fn configure(config: &mut web::ServiceConfig) {
+    config
+        .service(list_recordings)
+        .service(get_recording)
+        .service(delete_recording)
+        .service(set_encryption_key)
+        .service(get_game_data_chunk)
+        .service(get_key_frame)
+        .service(export_recording);
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecordingSummary {
+    pub platform_id: String,
+    pub game_id: String,
+    pub status: RecordStatus,
+    pub start_time: Option<String>,
+    // Milliseconds
+    pub duration: Option<u32>,
+    pub game_data_chunks: usize,
+    pub keyframes: usize,
+    // Records saved without it cannot be spectated or exported until it is set
+    pub has_encryption_key: bool,
+}
+
+impl From<&StoredRecord> for RecordingSummary {
+    fn from(record: &StoredRecord) -> Self {
+        RecordingSummary {
+            platform_id: record.endpoint.platform_id.clone(),
+            game_id: record.game_id.clone(),
+            status: record.status,
+            start_time: record
+                .metadata
+                .as_ref()
+                .map(|metadata| metadata.start_time.clone()),
+            duration: record
+                .metadata
+                .as_ref()
+                .map(|metadata| metadata.estimated_game_length(record.game_data_chunks.len())),
+            game_data_chunks: record.game_data_chunks.len(),
+            keyframes: record.keyframes.len(),
+            has_encryption_key: !record.encryption_key.is_empty(),
+        }
+    }
+}
+
+// Media data announced by the spectator host that the record lacks
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Completeness {
+    pub complete: bool,
+    pub missing_game_data_chunks: Vec<u32>,
+    pub missing_keyframes: Vec<u32>,
+}
+
+impl From<&StoredRecord> for Completeness {
+    fn from(record: &StoredRecord) -> Self {
+        let last_chunk_id = record.game_data_chunks.last().copied().unwrap_or(0);
+        let last_keyframe_id = record.keyframes.last().copied().unwrap_or(0);
+        // Chunks between the end of the startup and the start of the game do not exist
+        let expected_chunks: Vec<u32> = match &record.metadata {
+            Some(metadata) => (1..=metadata.end_startup_chunk_id.min(last_chunk_id))
+                .chain(metadata.start_game_chunk_id.max(1)..=last_chunk_id)
+                .collect(),
+            None => (1..=last_chunk_id).collect(),
+        };
+        let missing_game_data_chunks: Vec<u32> = expected_chunks
+            .into_iter()
+            .filter(|chunk_id| !record.game_data_chunks.contains(chunk_id))
+            .collect();
+        let missing_keyframes: Vec<u32> = (1..=last_keyframe_id)
+            .filter(|keyframe_id| !record.keyframes.contains(keyframe_id))
+            .collect();
+        Completeness {
+            complete: record.status == RecordStatus::Complete
+                && missing_game_data_chunks.is_empty()
+                && missing_keyframes.is_empty(),
+            missing_game_data_chunks,
+            missing_keyframes,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecordingDetails {
+    #[serde(flatten)]
+    pub summary: RecordingSummary,
+    pub game_data_chunk_ids: Vec<u32>,
+    pub keyframe_ids: Vec<u32>,
+    pub completeness: Completeness,
+    pub metadata: Option<GameMetaData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    platform_id: Option<String>,
+    status: Option<RecordStatus>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+#[get("/recordings")]
+async fn list_recordings(
+    library: web::Data<Library>,
+    query: web::Query<ListQuery>,
+) -> Result<impl Responder> {
+    let records = web::block(move || library.list()).await??;
+    let summaries: Vec<RecordingSummary> = records
+        .iter()
+        .filter(|record| {
+            query
+                .platform_id
+                .as_ref()
+                .is_none_or(|platform_id| &record.endpoint.platform_id == platform_id)
+        })
+        .filter(|record| query.status.is_none_or(|status| record.status == status))
+        .skip(query.offset.unwrap_or(0))
+        .take(query.limit.unwrap_or(usize::MAX))
+        .map(RecordingSummary::from)
+        .collect();
+    Ok(web::Json(summaries))
+}
+
+#[get("/recordings/{platformId}/{gameId}")]
+async fn get_recording(
+    library: web::Data<Library>,
+    game_key: web::Path<GameKey>,
+) -> Result<impl Responder> {
+    let GameKey {
+        game_id,
+        platform_id,
+    } = game_key.into_inner();
+    let record = web::block(move || library.get(&platform_id, game_id)).await??;
+    let record = &record.record;
+    Ok(web::Json(RecordingDetails {
+        summary: record.into(),
+        game_data_chunk_ids: record.game_data_chunks.clone(),
+        keyframe_ids: record.keyframes.clone(),
+        completeness: record.into(),
+        metadata: record.metadata.clone(),
+    }))
+}
+
+#[delete("/recordings/{platformId}/{gameId}")]
+async fn delete_recording(
+    library: web::Data<Library>,
+    game_key: web::Path<GameKey>,
+) -> Result<impl Responder> {
+    let GameKey {
+        game_id,
+        platform_id,
+    } = game_key.into_inner();
+    web::block(move || library.delete(&platform_id, game_id)).await??;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Deserialize)]
+struct EncryptionKeyRequest {
+    encryption_key: String,
+}
+
+// For records saved without the key, a game the relay still follows is updated in place
+#[put("/recordings/{platformId}/{gameId}/encryption_key")]
+async fn set_encryption_key(
+    library: web::Data<Library>,
+    relay: Option<web::Data<Relay>>,
+    game_key: web::Path<GameKey>,
+    body: web::Json<EncryptionKeyRequest>,
+) -> Result<impl Responder> {
+    let GameKey {
+        game_id,
+        platform_id,
+    } = game_key.into_inner();
+    let encryption_key = body.into_inner().encryption_key;
+    if let Some(relay) = relay {
+        let relayed = relay
+            .set_encryption_key(&library, &platform_id, game_id, encryption_key.clone())
+            .await?;
+        if relayed {
+            return Ok(HttpResponse::NoContent().finish());
+        }
+    }
+    web::block(move || library.set_encryption_key(&platform_id, game_id, encryption_key)).await??;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/recordings/{platformId}/{gameId}/game_data_chunks/{chunkId}")]
+async fn get_game_data_chunk(
+    library: web::Data<Library>,
+    path: web::Path<(String, u64, u32)>,
+) -> Result<impl Responder> {
+    let (platform_id, game_id, chunk_id) = path.into_inner();
+    let data = web::block(move || {
+        library
+            .get(&platform_id, game_id)?
+            .load_game_data_chunk(chunk_id)
+    })
+    .await??;
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .body(data))
+}
+
+#[get("/recordings/{platformId}/{gameId}/keyframes/{keyFrameId}")]
+async fn get_key_frame(
+    library: web::Data<Library>,
+    path: web::Path<(String, u64, u32)>,
+) -> Result<impl Responder> {
+    let (platform_id, game_id, keyframe_id) = path.into_inner();
+    let data = web::block(move || {
+        library
+            .get(&platform_id, game_id)?
+            .load_key_frame(keyframe_id)
+    })
+    .await??;
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .body(data))
+}
+
+// The whole recording as a .rofl replay file
+#[get("/recordings/{platformId}/{gameId}/rofl")]
+async fn export_recording(
+    library: web::Data<Library>,
+    game_key: web::Path<GameKey>,
+) -> Result<impl Responder> {
+    let GameKey {
+        game_id,
+        platform_id,
+    } = game_key.into_inner();
+    let filename = format!("{}-{}.rofl", platform_id, game_id);
+    let rofl = web::block(move || {
+        let record = library.get(&platform_id, game_id)?;
+        // Replay files without the key cannot be played
+        record.encryption_key()?;
+        let mut rofl = Vec::new();
+        rofl::export(&record.record, record.storage.as_ref(), &mut rofl).map_err(Error::Storage)?;
+        Ok::<_, Error>(rofl)
+    })
+    .await??;
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .body(rofl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::tests::{library_with_game, store_game};
+    use actix_web::http::StatusCode;
+    use actix_web::App;
+
+    #[actix_web::test]
+    async fn test_browse_and_delete_recordings() {
+        use actix_web::test;
+
+        let folder = tempfile::tempdir().unwrap();
+        store_game(folder.path(), 6654667051);
+        let library = library_with_game(folder.path());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .service(web::scope("/api").configure(configure)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/api/recordings?platform_id=KR&status=complete&offset=1")
+            .to_request();
+        let summaries: Vec<RecordingSummary> = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].game_id, "6654667051");
+        assert_eq!(summaries[0].duration, Some(60000));
+
+        let request = test::TestRequest::get()
+            .uri("/api/recordings/KR/6654667050")
+            .to_request();
+        let details: RecordingDetails = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(details.game_data_chunk_ids, vec![1, 2]);
+        assert!(details.completeness.complete);
+
+        let request = test::TestRequest::get()
+            .uri("/api/recordings/KR/6654667050/keyframes/1")
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        assert_eq!(body, "6654667050-keyframe1");
+
+        let request = test::TestRequest::get()
+            .uri("/api/recordings/KR/6654667050/rofl")
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let rofl = rofl::read_rofl(&mut std::io::Cursor::new(body)).unwrap();
+        assert_eq!(rofl.payload_header.game_id, 6654667050);
+        assert_eq!(rofl.entries.len(), 3);
+
+        let request = test::TestRequest::delete()
+            .uri("/api/recordings/KR/6654667050")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let request = test::TestRequest::get()
+            .uri("/api/recordings/KR/6654667050")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_completeness_skips_chunks_between_startup_and_game() {
+        let folder = tempfile::tempdir().unwrap();
+        let mut record = store_game(folder.path(), 6654667050);
+        let metadata = record.metadata.as_mut().unwrap();
+        metadata.end_startup_chunk_id = 1;
+        metadata.start_game_chunk_id = 3;
+        record.game_data_chunks = vec![1, 4];
+        record.keyframes = vec![2];
+
+        let completeness = Completeness::from(&record);
+        assert!(!completeness.complete);
+        assert_eq!(completeness.missing_game_data_chunks, vec![3]);
+        assert_eq!(completeness.missing_keyframes, vec![1]);
+    }
+}
@@ -0,0 +1,93 @@
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+// Keys from server.api_keys, no key disables the authentication
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys {
+    keys: Vec<String>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: Vec<String>) -> Self {
+        ApiKeys {
+            keys: keys.into_iter().filter(|key| !key.is_empty()).collect(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    // The key is sent as "Authorization: Bearer <key>", "X-API-Key: <key>" or, from a browser,
+    // as the password of "Authorization: Basic"
+    pub fn authorizes(&self, request: &HttpRequest) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        request_key(request).is_some_and(|key| {
+            self.keys
+                .iter()
+                .any(|allowed| constant_time_eq(allowed.as_bytes(), key.as_bytes()))
+        })
+    }
+}
+
+fn request_key(request: &HttpRequest) -> Option<String> {
+    let headers = request.headers();
+    if let Some(key) = headers.get("X-API-Key") {
+        return key.to_str().ok().map(str::to_string);
+    }
+
+    let authorization = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, credentials) = authorization.split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("bearer") {
+        Some(credentials.trim().to_string())
+    } else if scheme.eq_ignore_ascii_case("basic") {
+        let credentials = String::from_utf8(STANDARD.decode(credentials.trim()).ok()?).ok()?;
+        let (_user, password) = credentials.split_once(':')?;
+        Some(password.to_string())
+    } else {
+        None
+    }
+}
+
+// Does not tell how much of a key was guessed right through the response time
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_authorizes() {
+        let keys = ApiKeys::new(vec!["secret".to_string()]);
+
+        let request = TestRequest::default().to_http_request();
+        assert!(!keys.authorizes(&request));
+        let request = TestRequest::default()
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_http_request();
+        assert!(keys.authorizes(&request));
+        let request = TestRequest::default()
+            .insert_header(("X-API-Key", "secret"))
+            .to_http_request();
+        assert!(keys.authorizes(&request));
+        // admin:secret
+        let request = TestRequest::default()
+            .insert_header(("Authorization", "Basic YWRtaW46c2VjcmV0"))
+            .to_http_request();
+        assert!(keys.authorizes(&request));
+        let request = TestRequest::default()
+            .insert_header(("Authorization", "Bearer secre"))
+            .to_http_request();
+        assert!(!keys.authorizes(&request));
+
+        let request = TestRequest::default().to_http_request();
+        assert!(ApiKeys::new(vec![]).authorizes(&request));
+    }
+}
@@ -0,0 +1,570 @@
+use crate::error::Error;
+use crate::library::{Library, LibraryRecord};
+use crate::spectate::{launch_address, LaunchCommand, DEFAULT_GAME_DIR};
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder, Result};
+use log::warn;
+use lol_replay_common::crypto;
+use lol_replay_common::models::{ChunkInfo, GameKey, GameMetaData};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Recorded games served again as if they were live, mounted under /api
+pub fn configure(config: &mut web::ServiceConfig) {
+    config
+        .service(schedule_broadcast)
+        .service(list_broadcasts)
+        .service(cancel_broadcast);
+}
+
+// A recorded game replayed under a new game id, its chunks becoming available one chunk time
+// interval after the other from start + delay so that every spectator sees the same moment
+pub struct Broadcast {
+    pub record: Arc<LibraryRecord>,
+    pub game_id: u64,
+    // The chunk key of the recording encrypted with the new game id, as spectator clients derive
+    // it from the game id they spectate
+    pub encryption_key: String,
+    pub start: SystemTime,
+    pub delay: Duration,
+}
+
+impl Broadcast {
+    fn chunk_time_interval(&self) -> Result<u64, Error> {
+        let metadata = self.record.record.metadata.as_ref().ok_or_else(|| {
+            Error::InvalidRecord(format!(
+                "game {} has no metadata",
+                self.record.record.game_id
+            ))
+        })?;
+        Ok(u64::from(metadata.chunk_time_interval.max(1)))
+    }
+
+    // Milliseconds since the first chunk became available, None before that
+    fn elapsed(&self, now: SystemTime) -> Option<u64> {
+        now.duration_since(self.start + self.delay)
+            .ok()
+            .map(|elapsed| elapsed.as_millis() as u64)
+    }
+
+    pub fn chunk_info(&self, now: SystemTime) -> Result<ChunkInfo, Error> {
+        let interval = self.chunk_time_interval()?;
+        let chunks = &self.record.record.game_data_chunks;
+        let Some(elapsed) = self.elapsed(now) else {
+            let mut chunk_info = self.record.chunk_info(0, 0)?;
+            let until_first = (self.start + self.delay)
+                .duration_since(now)
+                .unwrap_or_default()
+                .as_millis();
+            chunk_info.next_available_chunk = until_first.min(u32::MAX as u128) as u32;
+            return Ok(chunk_info);
+        };
+
+        let available = ((elapsed / interval) as usize + 1).min(chunks.len());
+        let available_since = elapsed - (available as u64 - 1) * interval;
+        let mut chunk_info = self
+            .record
+            .chunk_info(chunks[available - 1], available_since)?;
+        chunk_info.next_available_chunk = if available < chunks.len() {
+            (interval - available_since) as u32
+        } else {
+            0
+        };
+        Ok(chunk_info)
+    }
+
+    pub fn metadata(&self, now: SystemTime) -> Result<GameMetaData, Error> {
+        let chunk_info = self.chunk_info(now)?;
+        let ended = chunk_info.end_game_chunk_id > 0;
+        let mut metadata = self.record.replay_metadata()?;
+        metadata.game_key.game_id = self.game_id;
+        metadata.encryption_key = self.encryption_key.clone();
+        metadata.delay_time = u32::try_from(self.delay.as_millis()).unwrap_or(u32::MAX);
+        metadata.game_ended = ended;
+        metadata.last_chunk_id = chunk_info.chunk_id;
+        metadata.last_key_frame_id = chunk_info.key_frame_id;
+        if !ended {
+            metadata.end_game_chunk_id = -1;
+            metadata.end_game_key_frame_id = -1;
+        }
+        Ok(metadata)
+    }
+
+    // Spectators who started late are still given one delay to catch up with the last chunk
+    fn expired(&self, now: SystemTime) -> bool {
+        let interval = self.chunk_time_interval().unwrap_or(1);
+        let chunks = self.record.record.game_data_chunks.len().max(1) as u64;
+        let last_chunk = Duration::from_millis((chunks - 1) * interval);
+        now >= self.start + self.delay + last_chunk + self.delay
+    }
+
+    // Chunks and keyframes are not served ahead of the broadcast
+    pub fn has_game_data_chunk(&self, chunk_id: u32, now: SystemTime) -> Result<bool, Error> {
+        Ok(chunk_id <= self.chunk_info(now)?.chunk_id)
+    }
+
+    pub fn has_key_frame(&self, keyframe_id: u32, now: SystemTime) -> Result<bool, Error> {
+        Ok(keyframe_id <= self.chunk_info(now)?.key_frame_id)
+    }
+}
+
+// Broadcasts scheduled through the API, lost on restart and dropped once expired
+pub struct Broadcasts {
+    scheduled: RwLock<HashMap<(String, u64), Arc<Broadcast>>>,
+    // Milliseconds since the epoch, far above the ids of real games
+    next_game_id: AtomicU64,
+}
+
+impl Default for Broadcasts {
+    fn default() -> Self {
+        Broadcasts {
+            scheduled: RwLock::new(HashMap::new()),
+            next_game_id: AtomicU64::new(unix_time(SystemTime::now()).as_millis() as u64),
+        }
+    }
+}
+
+impl Broadcasts {
+    pub fn get(&self, platform_id: &str, game_id: u64) -> Option<Arc<Broadcast>> {
+        self.scheduled
+            .read()
+            .unwrap()
+            .get(&(platform_id.to_string(), game_id))
+            .filter(|broadcast| !broadcast.expired(SystemTime::now()))
+            .cloned()
+    }
+
+    fn remove_expired(&self, now: SystemTime) {
+        self.scheduled
+            .write()
+            .unwrap()
+            .retain(|_, broadcast| !broadcast.expired(now));
+    }
+
+    // The delay defaults to the one the game was recorded with
+    pub fn schedule(
+        &self,
+        record: Arc<LibraryRecord>,
+        start: SystemTime,
+        delay: Option<Duration>,
+    ) -> Result<Arc<Broadcast>, Error> {
+        let metadata = record.replay_metadata()?;
+        if record.record.game_data_chunks.is_empty() {
+            return Err(Error::InvalidRecord(format!(
+                "game {} has no game data chunk",
+                record.record.game_id
+            )));
+        }
+        let invalid_key = |error: std::io::Error| {
+            Error::InvalidRecord(format!("invalid encryption key: {}", error))
+        };
+        let chunk_key = crypto::chunk_key(&record.record.game_id, record.encryption_key()?)
+            .map_err(invalid_key)?;
+        let game_id = self.next_game_id.fetch_add(1, Ordering::Relaxed);
+        let encryption_key =
+            crypto::encryption_key(&game_id.to_string(), &chunk_key).map_err(invalid_key)?;
+        let platform_id = record.record.endpoint.platform_id.clone();
+        self.remove_expired(SystemTime::now());
+        let broadcast = Arc::new(Broadcast {
+            record,
+            game_id,
+            encryption_key,
+            start,
+            delay: delay.unwrap_or(Duration::from_millis(u64::from(metadata.delay_time))),
+        });
+        self.scheduled
+            .write()
+            .unwrap()
+            .insert((platform_id, broadcast.game_id), broadcast.clone());
+        Ok(broadcast)
+    }
+
+    pub fn cancel(&self, platform_id: &str, game_id: u64) -> Option<Arc<Broadcast>> {
+        self.scheduled
+            .write()
+            .unwrap()
+            .remove(&(platform_id.to_string(), game_id))
+    }
+
+    pub fn list(&self) -> Vec<Arc<Broadcast>> {
+        self.remove_expired(SystemTime::now());
+        let mut broadcasts: Vec<_> = self.scheduled.read().unwrap().values().cloned().collect();
+        broadcasts.sort_by_key(|broadcast| (broadcast.start, broadcast.game_id));
+        broadcasts
+    }
+}
+
+fn unix_time(time: SystemTime) -> Duration {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+// Bounds of a schedule request, the delay is sent to spectator clients in milliseconds on 32 bits
+const MAX_START_IN: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+const MAX_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Deserialize)]
+struct ScheduleRequest {
+    platform_id: String,
+    // Id of the recorded game
+    game_id: u64,
+    // Seconds from now, the broadcast starts right away if unset
+    start_in: Option<u64>,
+    // Seconds spectators lag behind the start, the delay of the recording if unset
+    delay: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BroadcastSummary {
+    pub platform_id: String,
+    pub game_id: u64,
+    pub recorded_game_id: String,
+    // Seconds since the epoch
+    pub start_time: u64,
+    pub delay: u64,
+    pub game_ended: bool,
+    // Arguments of League of Legends.exe to spectate the broadcast
+    pub arguments: String,
+}
+
+impl BroadcastSummary {
+    fn new(broadcast: &Broadcast, address: &str, now: SystemTime) -> Result<Self, Error> {
+        let record = &broadcast.record.record;
+        let command = LaunchCommand::for_game(
+            address,
+            &record.endpoint.platform_id,
+            &broadcast.game_id.to_string(),
+            &broadcast.encryption_key,
+            DEFAULT_GAME_DIR,
+        )?;
+        Ok(BroadcastSummary {
+            platform_id: record.endpoint.platform_id.clone(),
+            game_id: broadcast.game_id,
+            recorded_game_id: record.game_id.clone(),
+            start_time: unix_time(broadcast.start).as_secs(),
+            delay: broadcast.delay.as_secs(),
+            game_ended: broadcast.metadata(now)?.game_ended,
+            arguments: command.arguments,
+        })
+    }
+}
+
+#[post("/broadcasts")]
+async fn schedule_broadcast(
+    request: HttpRequest,
+    library: web::Data<Library>,
+    broadcasts: web::Data<Broadcasts>,
+    body: web::Json<ScheduleRequest>,
+) -> Result<impl Responder> {
+    let ScheduleRequest {
+        platform_id,
+        game_id,
+        start_in,
+        delay,
+    } = body.into_inner();
+    // Checked before anything is scheduled
+    let address = launch_address(&request)?;
+    let start_in = Duration::from_secs(start_in.unwrap_or(0));
+    if start_in > MAX_START_IN {
+        return Err(Error::BadRequest(format!(
+            "start_in is limited to {} seconds",
+            MAX_START_IN.as_secs()
+        ))
+        .into());
+    }
+    let delay = delay.map(Duration::from_secs);
+    if delay.is_some_and(|delay| delay > MAX_DELAY) {
+        return Err(Error::BadRequest(format!(
+            "delay is limited to {} seconds",
+            MAX_DELAY.as_secs()
+        ))
+        .into());
+    }
+    let now = SystemTime::now();
+    let start = now
+        .checked_add(start_in)
+        .ok_or_else(|| Error::BadRequest("start_in is out of range".into()))?;
+    let record = web::block(move || library.get(&platform_id, game_id)).await??;
+    let broadcast = broadcasts.schedule(record, start, delay)?;
+    Ok(HttpResponse::Created().json(BroadcastSummary::new(&broadcast, &address, now)?))
+}
+
+#[get("/broadcasts")]
+async fn list_broadcasts(
+    request: HttpRequest,
+    broadcasts: web::Data<Broadcasts>,
+) -> Result<impl Responder> {
+    let address = launch_address(&request)?;
+    let now = SystemTime::now();
+    // A broadcast that cannot be described does not hide the others
+    let summaries = broadcasts
+        .list()
+        .iter()
+        .filter_map(
+            |broadcast| match BroadcastSummary::new(broadcast, &address, now) {
+                Ok(summary) => Some(summary),
+                Err(error) => {
+                    warn!(
+                        "Skipping broadcast {}/{}: {}",
+                        broadcast.record.record.endpoint.platform_id, broadcast.game_id, error
+                    );
+                    None
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+    Ok(web::Json(summaries))
+}
+
+#[delete("/broadcasts/{platformId}/{gameId}")]
+async fn cancel_broadcast(
+    broadcasts: web::Data<Broadcasts>,
+    game_key: web::Path<GameKey>,
+) -> Result<impl Responder> {
+    let GameKey {
+        game_id,
+        platform_id,
+    } = game_key.into_inner();
+    broadcasts
+        .cancel(&platform_id, game_id)
+        .ok_or(Error::GameNotFound {
+            platform_id,
+            game_id,
+        })?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::tests::{library_with_game, stored_record, ENCRYPTION_KEY};
+    use crate::observer;
+    use actix_web::http::StatusCode;
+    use actix_web::App;
+
+    #[test]
+    fn test_broadcast_key_gives_the_recorded_chunk_key() {
+        let folder = tempfile::tempdir().unwrap();
+        let record = stored_record(folder.path());
+        let broadcast = Broadcasts::default()
+            .schedule(record, SystemTime::now(), None)
+            .unwrap();
+
+        assert_ne!(broadcast.encryption_key, ENCRYPTION_KEY);
+        assert_eq!(
+            crypto::chunk_key(&broadcast.game_id.to_string(), &broadcast.encryption_key).unwrap(),
+            crypto::chunk_key("6654667050", ENCRYPTION_KEY).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_chunks_become_available_in_real_time() {
+        let folder = tempfile::tempdir().unwrap();
+        let record = stored_record(folder.path());
+        let broadcasts = Broadcasts::default();
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let broadcast = broadcasts.schedule(record, start, None).unwrap();
+        assert_eq!(broadcast.delay, Duration::from_secs(180));
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        let chunk_info = broadcast.chunk_info(at(0)).unwrap();
+        assert_eq!(chunk_info.chunk_id, 0);
+        assert_eq!(chunk_info.next_available_chunk, 180000);
+
+        let metadata = broadcast.metadata(at(190)).unwrap();
+        assert_eq!(metadata.game_key.game_id, broadcast.game_id);
+        assert_eq!(metadata.encryption_key, broadcast.encryption_key);
+        assert_eq!(metadata.last_chunk_id, 1);
+        assert!(!metadata.game_ended);
+        assert_eq!(metadata.end_game_chunk_id, -1);
+        let chunk_info = broadcast.chunk_info(at(190)).unwrap();
+        assert_eq!(chunk_info.available_since, 10000);
+        assert_eq!(chunk_info.next_available_chunk, 20000);
+        assert!(!broadcast.has_game_data_chunk(2, at(190)).unwrap());
+        assert!(!broadcast.has_key_frame(1, at(190)).unwrap());
+
+        let chunk_info = broadcast.chunk_info(at(215)).unwrap();
+        assert_eq!(chunk_info.chunk_id, 2);
+        assert_eq!(chunk_info.key_frame_id, 1);
+        assert_eq!(chunk_info.end_game_chunk_id, 2);
+        assert_eq!(chunk_info.next_available_chunk, 0);
+        assert!(broadcast.metadata(at(215)).unwrap().game_ended);
+        assert!(broadcast.has_game_data_chunk(2, at(215)).unwrap());
+    }
+
+    #[test]
+    fn test_expired_broadcasts_are_dropped() {
+        let folder = tempfile::tempdir().unwrap();
+        let record = stored_record(folder.path());
+        let broadcasts = Broadcasts::default();
+        let now = SystemTime::now();
+        let expired = broadcasts
+            .schedule(record.clone(), now - Duration::from_secs(3600), None)
+            .unwrap();
+        let live = broadcasts.schedule(record, now, None).unwrap();
+
+        // Last chunk after start + 210 seconds, one more delay of 180 seconds to catch up
+        assert!(!expired.expired(expired.start + Duration::from_secs(389)));
+        assert!(expired.expired(expired.start + Duration::from_secs(390)));
+        assert!(broadcasts.get("KR", expired.game_id).is_none());
+        assert!(broadcasts.get("KR", live.game_id).is_some());
+        let listed: Vec<_> = broadcasts
+            .list()
+            .iter()
+            .map(|broadcast| broadcast.game_id)
+            .collect();
+        assert_eq!(listed, vec![live.game_id]);
+        assert_eq!(broadcasts.scheduled.read().unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_list_skips_broken_broadcasts() {
+        use actix_web::test;
+
+        let folder = tempfile::tempdir().unwrap();
+        let record = stored_record(folder.path());
+        let broadcasts = Broadcasts::default();
+        let live = broadcasts
+            .schedule(record.clone(), SystemTime::now(), None)
+            .unwrap();
+        let mut without_metadata = record.record.clone();
+        without_metadata.metadata = None;
+        let broken = Broadcast {
+            record: Arc::new(LibraryRecord {
+                storage: without_metadata.storage.open_read_only().unwrap(),
+                record: without_metadata,
+            }),
+            game_id: live.game_id + 1,
+            encryption_key: live.encryption_key.clone(),
+            start: live.start,
+            delay: live.delay,
+        };
+        broadcasts
+            .scheduled
+            .write()
+            .unwrap()
+            .insert(("KR".to_string(), broken.game_id), Arc::new(broken));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(broadcasts))
+                .service(web::scope("/api").configure(configure)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/api/broadcasts")
+            .insert_header(("Host", "replays.local:8080"))
+            .to_request();
+        let summaries: Vec<BroadcastSummary> = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].game_id, live.game_id);
+
+        let request = test::TestRequest::get()
+            .uri("/api/broadcasts")
+            .insert_header(("Host", "replays.local:80 x"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_schedule_and_spectate_a_broadcast() {
+        use actix_web::test;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .app_data(web::Data::new(Broadcasts::default()))
+                .service(web::scope("/observer-mode/rest/consumer").configure(observer::configure))
+                .service(web::scope("/api").configure(configure)),
+        )
+        .await;
+
+        let request = test::TestRequest::post()
+            .uri("/api/broadcasts")
+            .set_json(
+                serde_json::json!({"platform_id": "KR", "game_id": 6654667050u64, "delay": 0}),
+            )
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let summary: BroadcastSummary = test::read_body_json(response).await;
+        assert_eq!(summary.recorded_game_id, "6654667050");
+        let broadcast_key = crypto::encryption_key(
+            &summary.game_id.to_string(),
+            &crypto::chunk_key("6654667050", ENCRYPTION_KEY).unwrap(),
+        )
+        .unwrap();
+        assert!(summary
+            .arguments
+            .ends_with(&format!("{} {} KR", broadcast_key, summary.game_id)));
+
+        // Spectated through the observer routes like any live game
+        let request = test::TestRequest::get()
+            .uri(&format!(
+                "/observer-mode/rest/consumer/getLastChunkInfo/KR/{}/0/token",
+                summary.game_id
+            ))
+            .to_request();
+        let chunk_info: ChunkInfo = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(chunk_info.chunk_id, 1);
+        assert_eq!(chunk_info.end_game_chunk_id, 0);
+
+        let chunk_uri = |chunk_id| {
+            format!(
+                "/observer-mode/rest/consumer/getGameDataChunk/KR/{}/{}/token",
+                summary.game_id, chunk_id
+            )
+        };
+        let request = test::TestRequest::get().uri(&chunk_uri(1)).to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        assert_eq!(body, "6654667050-chunk1");
+        let request = test::TestRequest::get().uri(&chunk_uri(2)).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let request = test::TestRequest::delete()
+            .uri(&format!("/api/broadcasts/KR/{}", summary.game_id))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let request = test::TestRequest::get().uri(&chunk_uri(1)).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_schedule_rejects_out_of_range_times() {
+        use actix_web::test;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let broadcasts = web::Data::new(Broadcasts::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .app_data(broadcasts.clone())
+                .service(web::scope("/api").configure(configure)),
+        )
+        .await;
+
+        for body in [
+            serde_json::json!({"platform_id": "KR", "game_id": 6654667050u64, "start_in": u64::MAX}),
+            serde_json::json!({"platform_id": "KR", "game_id": 6654667050u64, "delay": u64::MAX}),
+            serde_json::json!({"platform_id": "KR", "game_id": 6654667050u64, "delay": 4294968}),
+        ] {
+            let request = test::TestRequest::post()
+                .uri("/api/broadcasts")
+                .set_json(body)
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        assert!(broadcasts.list().is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    GameNotFound {
+        platform_id: String,
+        game_id: u64,
+    },
+    // The game is recorded but not this chunk or keyframe
+    MediaNotFound {
+        media_type: &'static str,
+        id: u32,
+    },
+    // The record file could not be decoded or lacks what the route needs
+    InvalidRecord(String),
+    Storage(io::Error),
+    Database(Box<dyn std::error::Error + Send + Sync>),
+    Config(Box<dyn std::error::Error + Send + Sync>),
+    // Missing or unknown API key on a protected route
+    Unauthorized,
+    // The spectator host relayed by the server failed or sent something unexpected
+    Upstream(String),
+    // A query parameter or header the route cannot use
+    BadRequest(String),
+    // Saved without the key its chunks are encrypted with, like games relayed from a spectator
+    // host that does not give it, until it is set through the API
+    MissingEncryptionKey {
+        platform_id: String,
+        game_id: String,
+    },
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::GameNotFound {
+                platform_id,
+                game_id,
+            } => write!(f, "game {} not found on platform {}", game_id, platform_id),
+            Error::MediaNotFound { media_type, id } => write!(f, "{} {} not found", media_type, id),
+            Error::InvalidRecord(message) => write!(f, "invalid record: {}", message),
+            Error::Storage(error) => write!(f, "storage error: {}", error),
+            Error::Database(error) => write!(f, "database error: {}", error),
+            Error::Config(error) => write!(f, "configuration error: {}", error),
+            Error::Unauthorized => write!(f, "a valid API key is required"),
+            Error::Upstream(message) => write!(f, "upstream spectator error: {}", message),
+            Error::BadRequest(message) => write!(f, "bad request: {}", message),
+            Error::MissingEncryptionKey {
+                platform_id,
+                game_id,
+            } => write!(
+                f,
+                "game {} on platform {} has no encryption key, set it with \
+                 PUT /api/recordings/{}/{}/encryption_key",
+                game_id, platform_id, platform_id, game_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Storage(error) => Some(error),
+            Error::Database(error) | Error::Config(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::GameNotFound { .. } | Error::MediaNotFound { .. } => StatusCode::NOT_FOUND,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Error::MissingEncryptionKey { .. } => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        // Lets browsers prompt for the key, given as the password
+        if let Error::Unauthorized = self {
+            response.insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"lol-replay\""));
+        }
+        response.json(ErrorBody {
+            error: self.to_string(),
+        })
+    }
+}
+
+impl Error {
+    // Reading a chunk or keyframe that is not in the storage is a 404
+    pub fn media(error: io::Error, media_type: &'static str, id: u32) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => Error::MediaNotFound { media_type, id },
+            _ => Error::Storage(error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::InvalidRecord(error.to_string())
+    }
+}
@@ -0,0 +1,647 @@
+use crate::error::Error;
+
+use log::warn;
+use lol_replay_common::config::Config;
+use lol_replay_common::crypto;
+use lol_replay_common::models::{ChunkInfo, GameMetaData};
+use lol_replay_common::record::{RecordStatus, StoredRecord};
+use lol_replay_common::storage::{game_folder, Storage, StorageDescriptor};
+use lol_replay_db::repository;
+use lol_replay_db::DbConnection;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+// Where the record files written by the client are looked up
+pub enum RecordSource {
+    Database(Mutex<DbConnection>),
+    // {path}/{platform_id}/{game_id}.json
+    Directory(PathBuf),
+    // {path}/{platform_id}/{game_id}/records/{game_id}.json, next to the media data, or
+    // {path}/{platform_id}/records/{game_id}.json for games recorded before each got its folder
+    RecordFolder(PathBuf),
+}
+
+impl RecordSource {
+    // Same precedence as the record sink of the client
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        if let Some(records_dir) = &config.storage.records_dir {
+            Ok(RecordSource::Directory(records_dir.clone()))
+        } else if let Some(database_url) = &config.database.url {
+            let mut conn = lol_replay_db::establish_connection(database_url)
+                .map_err(|error| Error::Database(Box::new(error)))?;
+            lol_replay_db::run_migrations(&mut conn).map_err(Error::Database)?;
+            Ok(RecordSource::Database(Mutex::new(conn)))
+        } else if let Some(record_folder) = &config.storage.record_folder {
+            Ok(RecordSource::RecordFolder(record_folder.clone()))
+        } else {
+            Err(Error::Config(
+                "storage.records_dir, database.url or storage.record_folder is required".into(),
+            ))
+        }
+    }
+
+    fn find(&self, platform_id: &str, game_id: u64) -> Result<Option<StoredRecord>, Error> {
+        if let RecordSource::Database(conn) = self {
+            let mut conn = conn.lock().unwrap();
+            let record = repository::find_record(&mut conn, platform_id, &game_id.to_string())
+                .map_err(|error| Error::Database(Box::new(error)))?;
+            return match record {
+                Some(record) => Ok(Some(record.to_stored_record()?)),
+                None => Ok(None),
+            };
+        }
+        for path in self.record_files(platform_id, game_id) {
+            match fs::read_to_string(path) {
+                Ok(json) => return Ok(Some(StoredRecord::from_json(&json)?)),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(Error::Storage(error)),
+            }
+        }
+        Ok(None)
+    }
+
+    // Where the record file of a game may be, in lookup order
+    fn record_files(&self, platform_id: &str, game_id: u64) -> Vec<PathBuf> {
+        let file_name = format!("{}.json", game_id);
+        match self {
+            RecordSource::Database(_) => Vec::new(),
+            RecordSource::Directory(path) => vec![path.join(platform_id).join(file_name)],
+            RecordSource::RecordFolder(path) => vec![
+                game_folder(path, platform_id, &game_id.to_string())
+                    .join("records")
+                    .join(&file_name),
+                path.join(platform_id).join("records").join(file_name),
+            ],
+        }
+    }
+
+    // Every record, the ones that cannot be read are skipped
+    fn list(&self) -> Result<Vec<StoredRecord>, Error> {
+        let path = match self {
+            RecordSource::Database(conn) => {
+                let mut conn = conn.lock().unwrap();
+                let records = repository::list_records(&mut conn)
+                    .map_err(|error| Error::Database(Box::new(error)))?;
+                return Ok(records
+                    .iter()
+                    .filter_map(|record| match record.to_stored_record() {
+                        Ok(record) => Some(record),
+                        Err(error) => {
+                            warn!("Skipping record {}: {}", record.game_id, error);
+                            None
+                        }
+                    })
+                    .collect());
+            }
+            RecordSource::Directory(path) | RecordSource::RecordFolder(path) => path,
+        };
+
+        let mut folders = Vec::new();
+        for platform in read_dir_if_exists(path)? {
+            let platform = platform.path();
+            if let RecordSource::RecordFolder(_) = self {
+                folders.push(platform.join("records"));
+                for game in read_dir_if_exists(&platform)? {
+                    let is_game_id = game
+                        .file_name()
+                        .to_str()
+                        .and_then(|name| name.parse::<u64>().ok())
+                        .is_some();
+                    if is_game_id {
+                        folders.push(game.path().join("records"));
+                    }
+                }
+            } else {
+                folders.push(platform);
+            }
+        }
+
+        let mut records = Vec::new();
+        for folder in folders {
+            for file in read_dir_if_exists(&folder)? {
+                let path = file.path();
+                if path.extension().is_none_or(|extension| extension != "json") {
+                    continue;
+                }
+                match fs::read_to_string(&path)
+                    .map_err(Error::Storage)
+                    .and_then(|json| Ok(StoredRecord::from_json(&json)?))
+                {
+                    Ok(record) => records.push(record),
+                    Err(error) => warn!("Skipping record {}: {}", path.display(), error),
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    fn delete(&self, platform_id: &str, game_id: u64) -> Result<(), Error> {
+        if let RecordSource::Database(conn) = self {
+            let mut conn = conn.lock().unwrap();
+            repository::delete_record(&mut conn, platform_id, &game_id.to_string())
+                .map_err(|error| Error::Database(Box::new(error)))?;
+            return Ok(());
+        }
+        for path in self.record_files(platform_id, game_id) {
+            ignore_not_found(fs::remove_file(path))?;
+        }
+        Ok(())
+    }
+
+    // Same layouts as the record sinks of the client
+    fn save(&self, record: &StoredRecord) -> Result<(), Error> {
+        let json = serde_json::to_vec(record)?;
+        match self {
+            RecordSource::Database(conn) => {
+                let mut conn = conn.lock().unwrap();
+                repository::upsert_stored_record(&mut conn, record).map_err(Error::Database)?;
+                Ok(())
+            }
+            RecordSource::Directory(path) => {
+                let folder = path.join(&record.endpoint.platform_id);
+                fs::create_dir_all(&folder).map_err(Error::Storage)?;
+                fs::write(folder.join(format!("{}.json", record.game_id)), json)
+                    .map_err(Error::Storage)
+            }
+            RecordSource::RecordFolder(_) => record
+                .storage
+                .open()
+                .and_then(|storage| storage.store_record(&record.game_id, json))
+                .map_err(Error::Storage),
+        }
+    }
+}
+
+// A key the chunk key of the game can be decrypted from
+pub fn check_encryption_key(game_id: &str, encryption_key: &str) -> Result<(), Error> {
+    crypto::chunk_key(game_id, encryption_key)
+        .map(|_| ())
+        .map_err(|error| Error::BadRequest(format!("invalid encryption key: {}", error)))
+}
+
+// The platform id ends up in paths
+pub fn is_valid_platform_id(platform_id: &str) -> bool {
+    !platform_id.is_empty() && platform_id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn read_dir_if_exists(path: &Path) -> Result<Vec<fs::DirEntry>, Error> {
+    match fs::read_dir(path) {
+        Ok(entries) => Ok(entries.filter_map(|entry| entry.ok()).collect()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(Error::Storage(error)),
+    }
+}
+
+pub struct LibraryRecord {
+    pub record: StoredRecord,
+    pub storage: Box<dyn Storage>,
+}
+
+impl LibraryRecord {
+    pub fn last_game_data_chunk_id(&self) -> u32 {
+        self.record.game_data_chunks.last().copied().unwrap_or(0)
+    }
+
+    pub fn last_key_frame_id(&self) -> u32 {
+        self.record.keyframes.last().copied().unwrap_or(0)
+    }
+
+    pub fn encryption_key(&self) -> Result<&str, Error> {
+        if self.record.encryption_key.is_empty() {
+            return Err(Error::MissingEncryptionKey {
+                platform_id: self.record.endpoint.platform_id.clone(),
+                game_id: self.record.game_id.clone(),
+            });
+        }
+        Ok(&self.record.encryption_key)
+    }
+
+    // {record_folder}/{platform_id}/{game_id}, not the platform folder of older records
+    fn has_own_storage(&self) -> bool {
+        match &self.record.storage {
+            StorageDescriptor::Disk { base_path } => {
+                base_path.file_name().and_then(|name| name.to_str())
+                    == Some(self.record.game_id.as_str())
+            }
+        }
+    }
+
+    // Only the media data the record lists, older records share their storage with the other
+    // games of the platform
+    pub fn load_game_data_chunk(&self, chunk_id: u32) -> Result<Vec<u8>, Error> {
+        if !self.record.game_data_chunks.contains(&chunk_id) {
+            return Err(Error::MediaNotFound {
+                media_type: "game data chunk",
+                id: chunk_id,
+            });
+        }
+        self.storage
+            .load_game_data_chunk(chunk_id)
+            .map_err(|error| Error::media(error, "game data chunk", chunk_id))
+    }
+
+    pub fn load_key_frame(&self, keyframe_id: u32) -> Result<Vec<u8>, Error> {
+        if !self.record.keyframes.contains(&keyframe_id) {
+            return Err(Error::MediaNotFound {
+                media_type: "keyframe",
+                id: keyframe_id,
+            });
+        }
+        self.storage
+            .load_key_frame(keyframe_id)
+            .map_err(|error| Error::media(error, "keyframe", keyframe_id))
+    }
+
+    fn metadata(&self) -> Result<&GameMetaData, Error> {
+        self.record.metadata.as_ref().ok_or_else(|| {
+            Error::InvalidRecord(format!("game {} has no metadata", self.record.game_id))
+        })
+    }
+
+    // The state of the game once chunk_id is the latest available chunk
+    pub fn chunk_info(&self, chunk_id: u32, available_since: u64) -> Result<ChunkInfo, Error> {
+        let metadata = self.metadata()?;
+        let (key_frame_id, next_chunk_id) = self
+            .record
+            .keyframes
+            .iter()
+            .map(|&keyframe_id| (keyframe_id, metadata.keyframe_next_chunk_id(keyframe_id)))
+            .take_while(|&(_, next_chunk_id)| next_chunk_id <= chunk_id)
+            .last()
+            .unwrap_or((0, 0));
+        let last_chunk_id = self.last_game_data_chunk_id();
+        Ok(ChunkInfo {
+            chunk_id,
+            available_since,
+            next_available_chunk: if chunk_id < last_chunk_id {
+                metadata.chunk_time_interval
+            } else {
+                0
+            },
+            key_frame_id,
+            next_chunk_id,
+            end_startup_chunk_id: metadata.end_startup_chunk_id,
+            start_game_chunk_id: metadata.start_game_chunk_id,
+            end_game_chunk_id: if chunk_id >= last_chunk_id {
+                last_chunk_id
+            } else {
+                0
+            },
+            duration: metadata.chunk_time_interval,
+        })
+    }
+
+    // What the spectator client gets for a finished replay, everything is already available
+    pub fn replay_metadata(&self) -> Result<GameMetaData, Error> {
+        let mut metadata = self.metadata()?.clone();
+        metadata.game_ended = true;
+        metadata.last_chunk_id = self.last_game_data_chunk_id();
+        metadata.last_key_frame_id = self.last_key_frame_id();
+        metadata.end_game_chunk_id = self.last_game_data_chunk_id() as i32;
+        metadata.end_game_key_frame_id = self.last_key_frame_id() as i32;
+        metadata.pending_available_chunk_info.clear();
+        metadata.pending_available_key_frame_info.clear();
+        Ok(metadata)
+    }
+}
+
+// Records shared by every worker, loaded on first request
+pub struct Library {
+    source: RecordSource,
+    // Where the client stores the media data, record files keep the path it was given
+    record_folder: Option<PathBuf>,
+    cache: RwLock<HashMap<(String, u64), Arc<LibraryRecord>>>,
+}
+
+impl Library {
+    pub fn new(source: RecordSource) -> Self {
+        let record_folder = match &source {
+            RecordSource::RecordFolder(path) => Some(path.clone()),
+            _ => None,
+        };
+        Library {
+            source,
+            record_folder,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // Media data of records kept in the database or records_dir
+    pub fn with_record_folder(mut self, record_folder: Option<PathBuf>) -> Self {
+        if self.record_folder.is_none() {
+            self.record_folder = record_folder;
+        }
+        self
+    }
+
+    // The storage path of a record is relative to where the client ran, look the media data up
+    // in the record folder instead
+    fn locate_storage(&self, record: &mut StoredRecord) {
+        let Some(record_folder) = &self.record_folder else {
+            return;
+        };
+        let platform_id = &record.endpoint.platform_id;
+        let folder = game_folder(record_folder, platform_id, &record.game_id);
+        let base_path = if folder.is_dir() {
+            folder
+        } else {
+            // Games recorded before each got its folder share the platform folder
+            record_folder.join(platform_id)
+        };
+        record.storage = StorageDescriptor::Disk { base_path };
+    }
+
+    // Blocking, call it from web::block
+    pub fn get(&self, platform_id: &str, game_id: u64) -> Result<Arc<LibraryRecord>, Error> {
+        let key = (platform_id.to_string(), game_id);
+        if let Some(record) = self.cache.read().unwrap().get(&key) {
+            return Ok(record.clone());
+        }
+
+        let not_found = || Error::GameNotFound {
+            platform_id: platform_id.to_string(),
+            game_id,
+        };
+        if !is_valid_platform_id(platform_id) {
+            return Err(not_found());
+        }
+        let mut record = self
+            .source
+            .find(platform_id, game_id)?
+            .ok_or_else(not_found)?;
+        self.locate_storage(&mut record);
+        let storage = record.storage.open_read_only().map_err(Error::Storage)?;
+        let record = Arc::new(LibraryRecord { record, storage });
+
+        // A game still being recorded is read again on the next request
+        if record.record.status != RecordStatus::Recording {
+            self.cache.write().unwrap().insert(key, record.clone());
+        }
+        Ok(record)
+    }
+
+    // Blocking, writes the record and drops its cached copy
+    pub fn save(&self, record: &StoredRecord) -> Result<(), Error> {
+        self.source.save(record)?;
+        if let Ok(game_id) = record.game_id.parse() {
+            self.cache
+                .write()
+                .unwrap()
+                .remove(&(record.endpoint.platform_id.clone(), game_id));
+        }
+        Ok(())
+    }
+
+    // Blocking, for records saved without their key
+    pub fn set_encryption_key(
+        &self,
+        platform_id: &str,
+        game_id: u64,
+        encryption_key: String,
+    ) -> Result<(), Error> {
+        let mut record = self.get(platform_id, game_id)?.record.clone();
+        check_encryption_key(&record.game_id, &encryption_key)?;
+        record.encryption_key = encryption_key;
+        self.save(&record)
+    }
+
+    // Blocking, read from the source on every call
+    pub fn list(&self) -> Result<Vec<StoredRecord>, Error> {
+        let mut records = self.source.list()?;
+        records.sort_by(|a, b| {
+            (&a.endpoint.platform_id, &a.game_id).cmp(&(&b.endpoint.platform_id, &b.game_id))
+        });
+        Ok(records)
+    }
+
+    // Removes the record and its media data, the media data of older records is kept as other
+    // games of the platform have files with the same ids
+    pub fn delete(&self, platform_id: &str, game_id: u64) -> Result<(), Error> {
+        let record = self.get(platform_id, game_id)?;
+        self.source.delete(platform_id, game_id)?;
+        self.cache
+            .write()
+            .unwrap()
+            .remove(&(platform_id.to_string(), game_id));
+
+        if !record.has_own_storage() {
+            warn!(
+                "Keeping the media data of {}/{}, its storage is shared with other games",
+                platform_id, game_id
+            );
+            return Ok(());
+        }
+        for chunk_id in &record.record.game_data_chunks {
+            ignore_not_found(record.storage.delete_game_data_chunk(*chunk_id))?;
+        }
+        for keyframe_id in &record.record.keyframes {
+            ignore_not_found(record.storage.delete_key_frame(*keyframe_id))?;
+        }
+        Ok(())
+    }
+}
+
+fn ignore_not_found(result: io::Result<()>) -> Result<(), Error> {
+    match result {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(Error::Storage(error)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use lol_replay_common::models::{GameKey, SpectatorEndpoint};
+    use lol_replay_common::record::FORMAT_VERSION;
+    use lol_replay_common::storage::DiskStorage;
+
+    pub const ENCRYPTION_KEY: &str = "oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6";
+    const METADATA: &str = r#"{"gameKey":{"gameId":6654667050,"platformId":"KR"},"gameServerAddress":"","port":0,"encryptionKey":"oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6","chunkTimeInterval":30000,"startTime":"","gameEnded":false,"lastChunkId":1,"lastKeyFrameId":1,"endStartupChunkId":1,"delayTime":180000,"pendingAvailableChunkInfo":[],"pendingAvailableKeyFrameInfo":[],"keyFrameTimeInterval":60000,"decodedEncryptionKey":"","startGameChunkId":1,"gameLength":0,"clientAddedLag":0,"clientBackFetchingEnabled":false,"clientBackFetchingFreq":1000,"interestScore":0,"featuredGame":false,"createTime":"","endGameChunkId":-1,"endGameKeyFrameId":-1}"#;
+
+    // A finished game with two chunks and a keyframe in {folder}/KR/{game_id}, named after the game
+    pub fn store_game(folder: &std::path::Path, game_id: u64) -> StoredRecord {
+        let storage = DiskStorage::new(game_folder(folder, "KR", &game_id.to_string())).unwrap();
+        for chunk_id in [1, 2] {
+            storage
+                .store_game_data_chunk(chunk_id, format!("{}-chunk{}", game_id, chunk_id).into())
+                .unwrap();
+        }
+        storage
+            .store_key_frame(1, format!("{}-keyframe1", game_id).into())
+            .unwrap();
+
+        let mut metadata: GameMetaData = serde_json::from_str(METADATA).unwrap();
+        metadata.game_key = GameKey {
+            game_id,
+            platform_id: "KR".to_string(),
+        };
+        let record = StoredRecord {
+            format_version: FORMAT_VERSION,
+            version: "2.0.0".to_string(),
+            game_version: None,
+            endpoint: SpectatorEndpoint::new("http://localhost".to_string(), "KR".to_string()),
+            game_id: game_id.to_string(),
+            encryption_key: metadata.encryption_key.clone(),
+            metadata: Some(metadata),
+            keyframes: vec![1],
+            game_data_chunks: vec![1, 2],
+            storage: storage.descriptor(),
+            status: RecordStatus::Complete,
+        };
+        storage
+            .store_record(&record.game_id, serde_json::to_vec(&record).unwrap())
+            .unwrap();
+        record
+    }
+
+    // A library over folder holding the game store_game writes for 6654667050
+    pub fn library_with_game(folder: &std::path::Path) -> Library {
+        store_game(folder, 6654667050);
+        Library::new(RecordSource::RecordFolder(folder.to_path_buf()))
+    }
+
+    pub fn stored_record(folder: &std::path::Path) -> Arc<LibraryRecord> {
+        library_with_game(folder).get("KR", 6654667050).unwrap()
+    }
+
+    #[test]
+    fn test_get_caches_records() {
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let keyframes = folder.path().join("KR/6654667050/keyframes");
+        fs::remove_dir_all(&keyframes).unwrap();
+
+        let record = library.get("KR", 6654667050).unwrap();
+        assert!(!keyframes.exists());
+        assert_eq!(record.last_game_data_chunk_id(), 2);
+        let metadata = record.replay_metadata().unwrap();
+        assert!(metadata.game_ended);
+        assert_eq!(metadata.end_game_chunk_id, 2);
+        let chunk_info = record.chunk_info(2, 0).unwrap();
+        assert_eq!(chunk_info.key_frame_id, 1);
+        assert_eq!(chunk_info.next_chunk_id, 2);
+        assert_eq!(chunk_info.end_game_chunk_id, 2);
+
+        fs::remove_file(folder.path().join("KR/6654667050/records/6654667050.json")).unwrap();
+        assert!(library.get("KR", 6654667050).is_ok());
+    }
+
+    #[test]
+    fn test_delete_removes_media_of_the_game_only() {
+        let folder = tempfile::tempdir().unwrap();
+        store_game(folder.path(), 6654667050);
+        store_game(folder.path(), 6654667051);
+        let library = Library::new(RecordSource::RecordFolder(folder.path().to_path_buf()));
+
+        library.delete("KR", 6654667050).unwrap();
+        assert!(matches!(
+            library.get("KR", 6654667050),
+            Err(Error::GameNotFound { .. })
+        ));
+        assert_eq!(library.list().unwrap().len(), 1);
+        let record = library.get("KR", 6654667051).unwrap();
+        assert_eq!(
+            record.load_game_data_chunk(1).unwrap(),
+            b"6654667051-chunk1"
+        );
+        assert_eq!(record.load_key_frame(1).unwrap(), b"6654667051-keyframe1");
+    }
+
+    #[test]
+    fn test_delete_keeps_media_shared_with_other_games() {
+        let folder = tempfile::tempdir().unwrap();
+        let storage = DiskStorage::new(folder.path().join("KR")).unwrap();
+        storage
+            .store_game_data_chunk(1, b"legacy-chunk1".to_vec())
+            .unwrap();
+        storage
+            .store_key_frame(1, b"legacy-keyframe1".to_vec())
+            .unwrap();
+        for game_id in [6654667050, 6654667051] {
+            let mut record = store_game(folder.path(), game_id);
+            record.storage = storage.descriptor();
+            record.game_data_chunks = vec![1];
+            storage
+                .store_record(&record.game_id, serde_json::to_vec(&record).unwrap())
+                .unwrap();
+            fs::remove_dir_all(game_folder(folder.path(), "KR", &record.game_id)).unwrap();
+        }
+        let library = Library::new(RecordSource::RecordFolder(folder.path().to_path_buf()));
+
+        library.delete("KR", 6654667050).unwrap();
+        assert!(matches!(
+            library.get("KR", 6654667050),
+            Err(Error::GameNotFound { .. })
+        ));
+        let record = library.get("KR", 6654667051).unwrap();
+        assert_eq!(record.load_game_data_chunk(1).unwrap(), b"legacy-chunk1");
+        assert_eq!(record.load_key_frame(1).unwrap(), b"legacy-keyframe1");
+    }
+
+    #[test]
+    fn test_reads_records_of_the_platform_folder() {
+        let folder = tempfile::tempdir().unwrap();
+        let storage = DiskStorage::new(folder.path().join("KR")).unwrap();
+        let mut record = store_game(folder.path(), 6654667050);
+        record.storage = storage.descriptor();
+        record.game_data_chunks = vec![1];
+        storage
+            .store_game_data_chunk(1, b"legacy-chunk1".to_vec())
+            .unwrap();
+        storage
+            .store_record(&record.game_id, serde_json::to_vec(&record).unwrap())
+            .unwrap();
+        fs::remove_dir_all(folder.path().join("KR/6654667050")).unwrap();
+        let library = Library::new(RecordSource::RecordFolder(folder.path().to_path_buf()));
+
+        assert_eq!(library.list().unwrap().len(), 1);
+        let record = library.get("KR", 6654667050).unwrap();
+        assert_eq!(record.load_game_data_chunk(1).unwrap(), b"legacy-chunk1");
+        assert!(matches!(
+            record.load_game_data_chunk(2),
+            Err(Error::MediaNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_reads_media_of_records_written_from_another_directory() {
+        let folder = tempfile::tempdir().unwrap();
+        let mut record = store_game(folder.path(), 6654667050);
+        // The client ran from the parent of its record folder, the server does not
+        record.storage = StorageDescriptor::Disk {
+            base_path: PathBuf::from("replays/KR/6654667050"),
+        };
+        let storage = DiskStorage::open(game_folder(folder.path(), "KR", "6654667050"));
+        storage
+            .store_record(&record.game_id, serde_json::to_vec(&record).unwrap())
+            .unwrap();
+        let library = Library::new(RecordSource::RecordFolder(folder.path().to_path_buf()));
+
+        let record = library.get("KR", 6654667050).unwrap();
+        assert_eq!(
+            record.load_game_data_chunk(2).unwrap(),
+            b"6654667050-chunk2"
+        );
+        assert!(!Path::new("replays").exists());
+    }
+
+    #[test]
+    fn test_get_unknown_game() {
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+
+        assert!(matches!(
+            library.get("KR", 1),
+            Err(Error::GameNotFound { .. })
+        ));
+        assert!(matches!(
+            library.get("..", 6654667050),
+            Err(Error::GameNotFound { .. })
+        ));
+        assert!(!folder.path().join("EUW1").exists());
+    }
+}
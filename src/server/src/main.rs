@@ -1,61 +1,263 @@
-use actix_web::{get, web, App, HttpResponse, HttpServer, Responder, Result};
-use serde::{Serialize, Deserialize};
+mod api;
+mod auth;
+mod broadcast;
+mod error;
+mod library;
+mod metrics;
+mod observer;
+mod relay;
+mod spectate;
+#[cfg(feature = "tls")]
+mod tls;
+mod ui;
 
-#[derive(Serialize, Deserialize)]
-struct MyObj {
-    game_id: String,
-    platform_id: String,
+use auth::ApiKeys;
+use broadcast::Broadcasts;
+use error::Error;
+use library::{Library, RecordSource};
+use relay::Relay;
+use spectate::ObserverAddress;
+
+use actix_web::dev::Service;
+use actix_web::{web, App, HttpServer};
+use clap::Parser;
+use log::{info, warn};
+use lol_replay_common::config::{BindAddresses, Config};
+
+use std::io;
+use std::path::PathBuf;
+
+const DEFAULT_BIND: &str = "127.0.0.1:8080";
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Cli {
+    // TOML config file, LOL_REPLAY_* variables override it
+    #[arg(long)]
+    config: Option<PathBuf>,
 }
 
-#[get("/version")]
-async fn version() -> impl Responder {
-    HttpResponse::Ok().body("2.0.0")
+#[actix_web::main]
+async fn main() -> io::Result<()> {
+    env_logger::init();
+    let args = Cli::parse();
+    let config = Config::load(args.config.as_deref()).map_err(invalid_input)?;
+    let addresses = config
+        .server
+        .bind
+        .as_ref()
+        .map(BindAddresses::addresses)
+        .unwrap_or_else(|| vec![DEFAULT_BIND]);
+    let http_addresses = config
+        .server
+        .http_bind
+        .as_ref()
+        .map(BindAddresses::addresses)
+        .unwrap_or_default();
+    let tls = match (&config.server.tls_cert, &config.server.tls_key) {
+        (Some(cert), Some(key)) => Some((cert, key)),
+        (None, None) => None,
+        _ => {
+            return Err(invalid_input(
+                "server.tls_cert and server.tls_key must be set together",
+            ))
+        }
+    };
+    if tls.is_some() && http_addresses.is_empty() {
+        warn!("server.http_bind is not set, the spectator client of the game cannot use HTTPS");
+    }
+    let source = RecordSource::from_config(&config).map_err(invalid_input)?;
+    let library = web::Data::new(
+        Library::new(source).with_record_folder(config.storage.record_folder.clone()),
+    );
+    let relay = match (&config.server.relay_url, &config.storage.record_folder) {
+        (Some(url), Some(record_folder)) => {
+            info!("Relaying games missing from the library from {}", url);
+            let relay =
+                Relay::new(url.clone(), record_folder.clone(), &config).map_err(invalid_input)?;
+            Some(web::Data::new(relay))
+        }
+        (Some(_), None) => {
+            return Err(invalid_input(
+                "server.relay_url requires storage.record_folder to store the relayed games",
+            ))
+        }
+        (None, _) => None,
+    };
+    let api_keys = ApiKeys::new(config.server.api_keys.clone().unwrap_or_default());
+    if !api_keys.is_enabled() {
+        warn!("server.api_keys is not set, every route is accessible without a key");
+    }
+
+    let observer_address =
+        web::Data::new(ObserverAddress::from_config(&config.server).map_err(invalid_input)?);
+
+    let broadcasts = web::Data::new(Broadcasts::default());
+    let app_data = move |config: &mut web::ServiceConfig| {
+        config
+            .app_data(library.clone())
+            .app_data(broadcasts.clone())
+            .app_data(observer_address.clone());
+        if let Some(relay) = &relay {
+            config.app_data(relay.clone());
+        }
+    };
+
+    let shared = app_data.clone();
+    let mut server = HttpServer::new(move || {
+        App::new()
+            .configure(&shared)
+            .configure(|config| configure(config, api_keys.clone()))
+    });
+    // Only the observer routes, so that no API key or admin route is ever sent in cleartext
+    let mut http_server = HttpServer::new(move || {
+        App::new()
+            .configure(&app_data)
+            .configure(configure_observer)
+    });
+    if let Some(workers) = config.server.workers {
+        server = server.workers(workers);
+        http_server = http_server.workers(workers);
+    }
+    for address in addresses {
+        server = match tls {
+            #[cfg(feature = "tls")]
+            Some((cert, key)) => server.bind_openssl(address, tls::acceptor(cert, key)?)?,
+            #[cfg(not(feature = "tls"))]
+            Some(_) => {
+                return Err(invalid_input(
+                    "HTTPS requires lol-replay-server to be built with the tls feature",
+                ))
+            }
+            None => server.bind(address)?,
+        };
+        info!(
+            "Listening on {}://{}",
+            if tls.is_some() { "https" } else { "http" },
+            address
+        );
+    }
+    if http_addresses.is_empty() {
+        return server.run().await;
+    }
+    for address in http_addresses {
+        http_server = http_server.bind(address)?;
+        info!("Listening on http://{} for the observer routes", address);
+    }
+    tokio::try_join!(server.run(), http_server.run()).map(|_| ())
 }
 
-#[get("/getGameMetaData/{platform_id}/{game_id}/{_}/token")]
-async fn get_game_meta_data(my_obj: web::Path<MyObj>) -> Result<impl Responder> {
-    Ok(web::Json(my_obj.into_inner()))
+fn invalid_input(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
 }
 
-// #[get("/getGameMetaData/<platform_id>/<game_id>/<_>/token")]
-// fn get_game_meta_data(platform_id: &str, game_id: &str) -> &'static str {
-//     "Hello, world!"
-// }
-
-// #[get("/getLastChunkInfo/<platform_id>/<game_id>/<_>/token")]
-// fn get_last_chunk_info(platform_id: &str, game_id: &str) -> &'static str {
-//     "Hello, world!"
-// }
-
-// #[get("/getGameDataChunk/<platform_id>/<game_id>/<chunk_id>/token")]
-// fn get_game_data_chunk(platform_id: &str, game_id: &str, chunk_id: &str) -> &'static str {
-//     "Hello, world!"
-// }
-
-// #[get("/getKeyFrame/<platform_id>/<game_id>/<keyframe_id>/token")]
-// fn get_key_frame(platform_id: &str, game_id: &str, keyframe_id: &str) -> &'static str {
-//     "Hello, world!"
-// }
-
-// #[launch]
-// fn rocket() -> _ {
-//     rocket::build()
-//         .mount(
-//             "/observer-mode/rest/consumer",
-//             routes![version, get_game_meta_data, get_last_chunk_info, get_game_data_chunk, get_key_frame]
-//         )
-// }
+// What the spectator client of the game requests, open to it on every address
+fn configure_observer(config: &mut web::ServiceConfig) {
+    config.service(web::scope("/observer-mode/rest/consumer").configure(observer::configure));
+}
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| {
-        App::new().service(
-            web::scope("/observer-mode/rest/consumer")
-                .service(version)
-                .service(get_game_meta_data),
+// The observer routes stay open to the game client and /metrics to Prometheus, every other route
+// requires an API key
+fn configure(config: &mut web::ServiceConfig, api_keys: ApiKeys) {
+    config
+        .configure(configure_observer)
+        .service(metrics::metrics)
+        .service(
+            web::scope("")
+                .wrap_fn(move |request, service| {
+                    let response = if api_keys.authorizes(request.request()) {
+                        Ok(service.call(request))
+                    } else {
+                        Err(request.error_response(Error::Unauthorized))
+                    };
+                    async move {
+                        match response {
+                            Ok(response) => response.await,
+                            Err(response) => Ok(response),
+                        }
+                    }
+                })
+                .configure(spectate::configure)
+                .service(
+                    web::scope("/api")
+                        .configure(api::configure)
+                        .configure(broadcast::configure),
+                )
+                .configure(ui::configure),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::tests::library_with_game;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn test_api_keys_protect_all_but_observer_routes_and_metrics() {
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let api_keys = ApiKeys::new(vec!["secret".to_string()]);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .configure(|config| configure(config, api_keys)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameMetaData/KR/6654667050/1/token")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = test::TestRequest::get().uri("/metrics").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for uri in ["/", "/api/recordings", "/spectate/KR/6654667050"] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+
+        let request = test::TestRequest::get()
+            .uri("/api/recordings")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_plain_http_serves_only_observer_routes() {
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .configure(configure_observer),
         )
-    })
-    .bind(("127.0.0.1", 8080))?
-    .run()
-    .await
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameMetaData/KR/6654667050/1/token")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for uri in [
+            "/",
+            "/metrics",
+            "/api/recordings",
+            "/spectate/KR/6654667050",
+        ] {
+            let request = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(("Authorization", "Bearer secret"))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+    }
 }
@@ -0,0 +1,25 @@
+use actix_web::{get, HttpResponse, Responder};
+use prometheus::{register_int_counter_vec, Encoder, IntCounterVec, TextEncoder};
+
+use std::sync::LazyLock;
+
+pub static REPLAYS_SERVED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lol_replay_replays_served_total",
+        "Replays requested by spectator clients",
+        &["platform_id"]
+    )
+    .unwrap()
+});
+
+#[get("/metrics")]
+pub async fn metrics() -> impl Responder {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    match encoder.encode(&prometheus::gather(), &mut buffer) {
+        Ok(()) => HttpResponse::Ok()
+            .content_type(encoder.format_type())
+            .body(buffer),
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+    }
+}
@@ -0,0 +1,304 @@
+use crate::broadcast::{Broadcast, Broadcasts};
+use crate::error::Error;
+use crate::library::{is_valid_platform_id, Library, LibraryRecord};
+use crate::metrics::REPLAYS_SERVED;
+use crate::relay::{Media, Relay};
+
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder, Result};
+use lol_replay_common::models::GameKey;
+use lol_replay_common::record::RecordStatus;
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+// The routes the spectator client of the game uses, mounted under /observer-mode/rest/consumer
+pub fn configure(config: &mut web::ServiceConfig) {
+    config
+        .service(version)
+        .service(get_game_meta_data)
+        .service(get_last_chunk_info)
+        .service(get_game_data_chunk)
+        .service(get_key_frame);
+}
+
+enum Game {
+    Recorded(Arc<LibraryRecord>),
+    // Not recorded yet, or still being recorded, with server.relay_url set
+    Relayed(web::Data<Relay>),
+    // A recorded game scheduled again under a new id
+    Broadcast(Arc<Broadcast>),
+}
+
+async fn find(
+    library: &web::Data<Library>,
+    relay: Option<web::Data<Relay>>,
+    broadcasts: Option<web::Data<Broadcasts>>,
+    platform_id: &str,
+    game_id: u64,
+) -> Result<Game> {
+    if let Some(broadcast) = broadcasts.and_then(|broadcasts| broadcasts.get(platform_id, game_id))
+    {
+        return Ok(Game::Broadcast(broadcast));
+    }
+    let found = {
+        let library = library.clone();
+        let platform_id = platform_id.to_string();
+        web::block(move || library.get(&platform_id, game_id)).await?
+    };
+    match (found, relay) {
+        (Ok(record), Some(relay)) if record.record.status == RecordStatus::Recording => {
+            Ok(Game::Relayed(relay))
+        }
+        (Ok(record), _) => Ok(Game::Recorded(record)),
+        (Err(Error::GameNotFound { .. }), Some(relay)) if is_valid_platform_id(platform_id) => {
+            Ok(Game::Relayed(relay))
+        }
+        (Err(error), _) => Err(error.into()),
+    }
+}
+
+// The upstream is asked for the very same path
+fn upstream_path(request: &HttpRequest) -> &str {
+    request
+        .uri()
+        .path_and_query()
+        .map_or_else(|| request.path(), |path| path.as_str())
+}
+
+#[get("/version")]
+async fn version() -> impl Responder {
+    HttpResponse::Ok().body("2.0.0")
+}
+
+// Spectator clients start every replay by fetching its metadata
+#[get("/getGameMetaData/{platformId}/{gameId}/{_}/token")]
+async fn get_game_meta_data(
+    library: web::Data<Library>,
+    relay: Option<web::Data<Relay>>,
+    broadcasts: Option<web::Data<Broadcasts>>,
+    request: HttpRequest,
+    game_key: web::Path<GameKey>,
+) -> Result<HttpResponse> {
+    let GameKey {
+        game_id,
+        platform_id,
+    } = game_key.into_inner();
+    let response = match find(&library, relay, broadcasts, &platform_id, game_id).await? {
+        Game::Recorded(record) => HttpResponse::Ok().json(record.replay_metadata()?),
+        Game::Broadcast(broadcast) => {
+            HttpResponse::Ok().json(broadcast.metadata(SystemTime::now())?)
+        }
+        Game::Relayed(relay) => {
+            let body = relay
+                .game_meta_data(&library, &platform_id, game_id, upstream_path(&request))
+                .await?;
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .body(body)
+        }
+    };
+    REPLAYS_SERVED.with_label_values(&[&platform_id]).inc();
+    Ok(response)
+}
+
+#[get("/getLastChunkInfo/{platformId}/{gameId}/{_}/token")]
+async fn get_last_chunk_info(
+    library: web::Data<Library>,
+    relay: Option<web::Data<Relay>>,
+    broadcasts: Option<web::Data<Broadcasts>>,
+    request: HttpRequest,
+    game_key: web::Path<GameKey>,
+) -> Result<HttpResponse> {
+    let GameKey {
+        game_id,
+        platform_id,
+    } = game_key.into_inner();
+    match find(&library, relay, broadcasts, &platform_id, game_id).await? {
+        Game::Recorded(record) => {
+            let chunk_info = record.chunk_info(record.last_game_data_chunk_id(), 0)?;
+            Ok(HttpResponse::Ok().json(chunk_info))
+        }
+        Game::Broadcast(broadcast) => {
+            Ok(HttpResponse::Ok().json(broadcast.chunk_info(SystemTime::now())?))
+        }
+        Game::Relayed(relay) => {
+            let body = relay
+                .last_chunk_info(&library, &platform_id, game_id, upstream_path(&request))
+                .await?;
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .body(body))
+        }
+    }
+}
+
+#[get("/getGameDataChunk/{platformId}/{gameId}/{chunkId}/token")]
+async fn get_game_data_chunk(
+    library: web::Data<Library>,
+    relay: Option<web::Data<Relay>>,
+    broadcasts: Option<web::Data<Broadcasts>>,
+    request: HttpRequest,
+    path: web::Path<(String, u64, u32)>,
+) -> Result<HttpResponse> {
+    let (platform_id, game_id, chunk_id) = path.into_inner();
+    match find(&library, relay, broadcasts, &platform_id, game_id).await? {
+        Game::Recorded(record) => {
+            let data = web::block(move || record.load_game_data_chunk(chunk_id)).await??;
+            Ok(media_response(data))
+        }
+        Game::Broadcast(broadcast) => {
+            if !broadcast.has_game_data_chunk(chunk_id, SystemTime::now())? {
+                return Err(Error::MediaNotFound {
+                    media_type: "game data chunk",
+                    id: chunk_id,
+                }
+                .into());
+            }
+            let data =
+                web::block(move || broadcast.record.load_game_data_chunk(chunk_id)).await??;
+            Ok(media_response(data))
+        }
+        Game::Relayed(relay) => {
+            let path = upstream_path(&request);
+            let data = relay
+                .media(
+                    &library,
+                    &platform_id,
+                    game_id,
+                    Media::GameDataChunk,
+                    chunk_id,
+                    path,
+                )
+                .await?;
+            Ok(media_response(data.into()))
+        }
+    }
+}
+
+#[get("/getKeyFrame/{platformId}/{gameId}/{keyFrameId}/token")]
+async fn get_key_frame(
+    library: web::Data<Library>,
+    relay: Option<web::Data<Relay>>,
+    broadcasts: Option<web::Data<Broadcasts>>,
+    request: HttpRequest,
+    path: web::Path<(String, u64, u32)>,
+) -> Result<HttpResponse> {
+    let (platform_id, game_id, keyframe_id) = path.into_inner();
+    match find(&library, relay, broadcasts, &platform_id, game_id).await? {
+        Game::Recorded(record) => {
+            let data = web::block(move || record.load_key_frame(keyframe_id)).await??;
+            Ok(media_response(data))
+        }
+        Game::Broadcast(broadcast) => {
+            if !broadcast.has_key_frame(keyframe_id, SystemTime::now())? {
+                return Err(Error::MediaNotFound {
+                    media_type: "keyframe",
+                    id: keyframe_id,
+                }
+                .into());
+            }
+            let data = web::block(move || broadcast.record.load_key_frame(keyframe_id)).await??;
+            Ok(media_response(data))
+        }
+        Game::Relayed(relay) => {
+            let path = upstream_path(&request);
+            let data = relay
+                .media(
+                    &library,
+                    &platform_id,
+                    game_id,
+                    Media::KeyFrame,
+                    keyframe_id,
+                    path,
+                )
+                .await?;
+            Ok(media_response(data.into()))
+        }
+    }
+}
+
+fn media_response(data: Vec<u8>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .body(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::tests::{library_with_game, store_game};
+    use crate::metrics::metrics;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use lol_replay_common::models::{ChunkInfo, GameMetaData};
+
+    #[actix_web::test]
+    async fn test_serves_recorded_games() {
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .service(metrics)
+                .service(web::scope("/observer-mode/rest/consumer").configure(configure)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameMetaData/KR/6654667050/1/token")
+            .to_request();
+        let metadata: GameMetaData = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(metadata.game_key.game_id, 6654667050);
+        assert_eq!(metadata.last_chunk_id, 2);
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getLastChunkInfo/KR/6654667050/30000/token")
+            .to_request();
+        let chunk_info: ChunkInfo = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(chunk_info.chunk_id, 2);
+        assert_eq!(chunk_info.end_game_chunk_id, 2);
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameDataChunk/KR/6654667050/2/token")
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        assert_eq!(body, "6654667050-chunk2");
+
+        let request = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#"lol_replay_replays_served_total{platform_id="KR"} 1"#));
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_games_and_media_are_not_found() {
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .service(web::scope("/observer-mode/rest/consumer").configure(configure)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameMetaData/KR/1/1/token")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = test::read_body(response).await;
+        assert_eq!(body, r#"{"error":"game 1 not found on platform KR"}"#);
+
+        // On disk but not part of the record
+        let record = store_game(folder.path(), 6654667050);
+        let storage = record.storage.open().unwrap();
+        storage.store_key_frame(7, b"keyframe7".to_vec()).unwrap();
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getKeyFrame/KR/6654667050/7/token")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = test::read_body(response).await;
+        assert_eq!(body, r#"{"error":"keyframe 7 not found"}"#);
+    }
+}
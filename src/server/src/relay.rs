@@ -0,0 +1,857 @@
+use crate::error::Error;
+use crate::library::{check_encryption_key, Library};
+
+use actix_web::web::{self, Bytes};
+use actix_web::Result;
+use log::{debug, info, warn};
+use lol_replay_common::config::Config;
+use lol_replay_common::models::{ChunkInfo, GameMetaData, SpectatorEndpoint};
+use lol_replay_common::rate_limit::{RateLimit, RateLimiter};
+use lol_replay_common::record::{RecordStatus, StoredRecord, FORMAT_VERSION};
+use lol_replay_common::storage::{game_folder, DiskStorage, Storage};
+use reqwest::{StatusCode, Url};
+
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// Games nobody asked for this long are forgotten, their record stays in the library and the
+// next request for them picks it up again
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// Proxies the observer routes of games that are not recorded yet to an upstream spectator host,
+// keeping every chunk and keyframe fetched in the record folder
+pub struct Relay {
+    client: reqwest::Client,
+    rate_limiter: RateLimiter,
+    base_url: String,
+    // host:port of the upstream, the key of its rate limit
+    host: String,
+    record_folder: PathBuf,
+    idle_timeout: Duration,
+    // Spectator API version of the upstream, asked once it answered
+    version: Mutex<Option<String>>,
+    // Games the upstream confirmed, followed until it announces their end or they go idle
+    games: Mutex<HashMap<(String, u64), Arc<RelayedGame>>>,
+}
+
+struct RelayedGame {
+    record: Mutex<StoredRecord>,
+    storage: Box<dyn Storage>,
+    last_access: Mutex<Instant>,
+    // The last chunk and keyframe, once the upstream announced the end of the game
+    end: Mutex<Option<(u32, u32)>>,
+}
+
+#[derive(Clone, Copy)]
+pub enum Media {
+    GameDataChunk,
+    KeyFrame,
+}
+
+impl Media {
+    fn name(self) -> &'static str {
+        match self {
+            Media::GameDataChunk => "game data chunk",
+            Media::KeyFrame => "keyframe",
+        }
+    }
+
+    fn ids(self, record: &mut StoredRecord) -> &mut Vec<u32> {
+        match self {
+            Media::GameDataChunk => &mut record.game_data_chunks,
+            Media::KeyFrame => &mut record.keyframes,
+        }
+    }
+
+    fn load(self, storage: &dyn Storage, id: u32) -> io::Result<Vec<u8>> {
+        match self {
+            Media::GameDataChunk => storage.load_game_data_chunk(id),
+            Media::KeyFrame => storage.load_key_frame(id),
+        }
+    }
+
+    fn store(self, storage: &dyn Storage, id: u32, data: Vec<u8>) -> io::Result<()> {
+        match self {
+            Media::GameDataChunk => storage.store_game_data_chunk(id, data),
+            Media::KeyFrame => storage.store_key_frame(id, data),
+        }
+    }
+}
+
+impl Relay {
+    // Requests to the upstream follow the [concurrency] limits of the config, like the client
+    pub fn new(base_url: String, record_folder: PathBuf, config: &Config) -> Result<Self, Error> {
+        let invalid = |error: String| Error::Config(error.into());
+        let url = Url::parse(&base_url).map_err(|error| invalid(error.to_string()))?;
+        let host = format!(
+            "{}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port_or_known_default().unwrap_or_default()
+        );
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|error| invalid(error.to_string()))?;
+        let defaults = RateLimit::default();
+        let rate_limit = RateLimit {
+            requests_per_second: config
+                .concurrency
+                .requests_per_second
+                .unwrap_or(defaults.requests_per_second),
+            max_in_flight: config
+                .concurrency
+                .max_in_flight
+                .unwrap_or(defaults.max_in_flight),
+        };
+        Ok(Relay {
+            client,
+            rate_limiter: RateLimiter::new(rate_limit),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            host,
+            record_folder,
+            idle_timeout: IDLE_TIMEOUT,
+            version: Mutex::new(None),
+            games: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // None when the upstream does not know the path
+    async fn fetch(&self, path: &str) -> Result<Option<Bytes>, Error> {
+        let url = format!("{}{}", self.base_url, path);
+        debug!("Relaying {}", url);
+        let upstream = |error: reqwest::Error| Error::Upstream(error.to_string());
+        let _permit = self.rate_limiter.acquire(&self.host).await;
+        let response = self.client.get(&url).send().await.map_err(upstream)?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response.bytes().await.map_err(upstream)?)),
+            status => Err(Error::Upstream(format!("{} returned {}", url, status))),
+        }
+    }
+
+    // Empty if the upstream does not tell, asked again for the next game then
+    async fn version(&self) -> String {
+        if let Some(version) = self.version.lock().unwrap().clone() {
+            return version;
+        }
+        match self.fetch("/observer-mode/rest/consumer/version").await {
+            Ok(Some(version)) => {
+                let version = String::from_utf8_lossy(&version).into_owned();
+                *self.version.lock().unwrap() = Some(version.clone());
+                version
+            }
+            _ => String::new(),
+        }
+    }
+
+    // A game already followed, idle ones are dropped on the way
+    fn followed(&self, platform_id: &str, game_id: u64) -> Option<Arc<RelayedGame>> {
+        let mut games = self.games.lock().unwrap();
+        games.retain(|(platform_id, game_id), game| {
+            let idle = game.last_access.lock().unwrap().elapsed() >= self.idle_timeout;
+            if idle {
+                info!("Forgetting idle game {} of {}", game_id, platform_id);
+            }
+            !idle
+        });
+        let game = games.get(&(platform_id.to_string(), game_id))?;
+        *game.last_access.lock().unwrap() = Instant::now();
+        Some(game.clone())
+    }
+
+    // Only called once the upstream answered for the game
+    async fn game(
+        &self,
+        library: &web::Data<Library>,
+        platform_id: &str,
+        game_id: u64,
+    ) -> Result<Arc<RelayedGame>> {
+        if let Some(game) = self.followed(platform_id, game_id) {
+            return Ok(game);
+        }
+
+        let version = self.version().await;
+        let library = library.clone();
+        let endpoint = SpectatorEndpoint::new(self.base_url.clone(), platform_id.to_string());
+        let base_path = game_folder(&self.record_folder, platform_id, &game_id.to_string());
+        let game = web::block(move || {
+            // A game relayed before a restart carries on in the same record
+            let (record, storage) = match library.get(&endpoint.platform_id, game_id) {
+                // Located by the library in the record folder, with the folders relayed before
+                Ok(existing) => (
+                    existing.record.clone(),
+                    existing
+                        .record
+                        .storage
+                        .open_read_only()
+                        .map_err(Error::Storage)?,
+                ),
+                Err(Error::GameNotFound { .. }) => {
+                    let storage = DiskStorage::new(base_path).map_err(Error::Storage)?;
+                    let record = StoredRecord {
+                        format_version: FORMAT_VERSION,
+                        version,
+                        game_version: None,
+                        endpoint,
+                        game_id: game_id.to_string(),
+                        encryption_key: String::new(),
+                        metadata: None,
+                        keyframes: Vec::new(),
+                        game_data_chunks: Vec::new(),
+                        storage: storage.descriptor(),
+                        status: RecordStatus::Recording,
+                    };
+                    (record, Box::new(storage) as Box<dyn Storage>)
+                }
+                Err(error) => return Err(error),
+            };
+            Ok(RelayedGame {
+                record: Mutex::new(record),
+                storage,
+                last_access: Mutex::new(Instant::now()),
+                end: Mutex::new(None),
+            })
+        })
+        .await??;
+
+        info!("Relaying game {} of {}", game_id, platform_id);
+        let mut games = self.games.lock().unwrap();
+        Ok(games
+            .entry((platform_id.to_string(), game_id))
+            .or_insert_with(|| Arc::new(game))
+            .clone())
+    }
+
+    // Saved while holding the record lock, so a request never overwrites the changes of a later one.
+    // An ended game is complete, and replayed from the library, once its last media data is stored.
+    async fn update<F>(
+        &self,
+        library: &web::Data<Library>,
+        platform_id: &str,
+        game_id: u64,
+        game: Arc<RelayedGame>,
+        update: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&mut StoredRecord) + Send + 'static,
+    {
+        let library = library.clone();
+        let updated = game.clone();
+        let complete = web::block(move || {
+            let mut record = updated.record.lock().unwrap();
+            update(&mut record);
+            if let Some((chunk_id, keyframe_id)) = *updated.end.lock().unwrap() {
+                if record.game_data_chunks.contains(&chunk_id)
+                    && (keyframe_id == 0 || record.keyframes.contains(&keyframe_id))
+                {
+                    record.status = RecordStatus::Complete;
+                }
+            }
+            library.save(&record)?;
+            Ok::<_, Error>(record.status == RecordStatus::Complete)
+        })
+        .await??;
+
+        if complete {
+            let mut games = self.games.lock().unwrap();
+            let key = (platform_id.to_string(), game_id);
+            if matches!(games.get(&key), Some(followed) if Arc::ptr_eq(followed, &game)) {
+                info!("Game {} of {} ended", game_id, platform_id);
+                if game.record.lock().unwrap().encryption_key.is_empty() {
+                    warn!(
+                        "Game {} of {} was recorded without its encryption key, set it with \
+                         PUT /api/recordings/{}/{}/encryption_key",
+                        game_id, platform_id, platform_id, game_id
+                    );
+                }
+                games.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    // Upstream metadata usually lacks the key, it is only given to the spectator client on its
+    // command line. False when the game is not followed, its record is then in the library only.
+    pub async fn set_encryption_key(
+        &self,
+        library: &web::Data<Library>,
+        platform_id: &str,
+        game_id: u64,
+        encryption_key: String,
+    ) -> Result<bool> {
+        let Some(game) = self.followed(platform_id, game_id) else {
+            return Ok(false);
+        };
+        check_encryption_key(&game_id.to_string(), &encryption_key)?;
+        self.update(library, platform_id, game_id, game, move |record| {
+            record.encryption_key = encryption_key;
+        })
+        .await?;
+        Ok(true)
+    }
+
+    pub async fn game_meta_data(
+        &self,
+        library: &web::Data<Library>,
+        platform_id: &str,
+        game_id: u64,
+        path: &str,
+    ) -> Result<Bytes> {
+        let body = self.fetch(path).await?.ok_or_else(|| Error::GameNotFound {
+            platform_id: platform_id.to_string(),
+            game_id,
+        })?;
+        let metadata: GameMetaData = serde_json::from_slice(&body)
+            .map_err(|error| Error::Upstream(format!("invalid game metadata: {}", error)))?;
+
+        let game = self.game(library, platform_id, game_id).await?;
+        self.update(library, platform_id, game_id, game, move |record| {
+            // Usually empty, see set_encryption_key
+            if record.encryption_key.is_empty() {
+                record.encryption_key = metadata.encryption_key.clone();
+            }
+            record.metadata = Some(metadata);
+        })
+        .await?;
+        Ok(body)
+    }
+
+    pub async fn last_chunk_info(
+        &self,
+        library: &web::Data<Library>,
+        platform_id: &str,
+        game_id: u64,
+        path: &str,
+    ) -> Result<Bytes> {
+        let body = self.fetch(path).await?.ok_or_else(|| Error::GameNotFound {
+            platform_id: platform_id.to_string(),
+            game_id,
+        })?;
+        let chunk_info: ChunkInfo = serde_json::from_slice(&body)
+            .map_err(|error| Error::Upstream(format!("invalid chunk info: {}", error)))?;
+
+        let ended =
+            chunk_info.end_game_chunk_id > 0 && chunk_info.chunk_id >= chunk_info.end_game_chunk_id;
+        if ended {
+            if let Some(game) = self.followed(platform_id, game_id) {
+                // Still relayed until the spectator client fetched the end of the game
+                *game.end.lock().unwrap() =
+                    Some((chunk_info.end_game_chunk_id, chunk_info.key_frame_id));
+                self.update(library, platform_id, game_id, game, |_| {})
+                    .await?;
+            }
+        }
+        Ok(body)
+    }
+
+    // Served from the storage once fetched, so each chunk is requested upstream only once
+    pub async fn media(
+        &self,
+        library: &web::Data<Library>,
+        platform_id: &str,
+        game_id: u64,
+        media: Media,
+        id: u32,
+        path: &str,
+    ) -> Result<Bytes> {
+        if let Some(game) = self.followed(platform_id, game_id) {
+            let stored = media.ids(&mut game.record.lock().unwrap()).contains(&id);
+            if stored {
+                let data = web::block(move || media.load(game.storage.as_ref(), id)).await?;
+                if let Ok(data) = data {
+                    return Ok(data.into());
+                }
+            }
+        }
+
+        let body = self.fetch(path).await?.ok_or(Error::MediaNotFound {
+            media_type: media.name(),
+            id,
+        })?;
+        let game = self.game(library, platform_id, game_id).await?;
+        let data = body.to_vec();
+        let stored = game.clone();
+        web::block(move || media.store(stored.storage.as_ref(), id, data))
+            .await?
+            .map_err(Error::Storage)?;
+        self.update(library, platform_id, game_id, game, move |record| {
+            let ids = media.ids(record);
+            if let Err(index) = ids.binary_search(&id) {
+                ids.insert(index, id);
+            }
+        })
+        .await?;
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::RecordSource;
+    use crate::observer::configure;
+    use crate::{api, spectate};
+    use actix_web::http::StatusCode;
+    use actix_web::App;
+    use lol_replay_common::crypto;
+    use lol_replay_common::storage::StorageDescriptor;
+    use mockito::Server;
+    use std::path::Path;
+
+    const METADATA: &str = r#"{"gameKey":{"gameId":42,"platformId":"EUW1"},"gameServerAddress":"","port":0,"encryptionKey":"key","chunkTimeInterval":30000,"startTime":"","gameEnded":false,"lastChunkId":1,"lastKeyFrameId":0,"endStartupChunkId":1,"delayTime":180000,"pendingAvailableChunkInfo":[],"pendingAvailableKeyFrameInfo":[],"keyFrameTimeInterval":60000,"decodedEncryptionKey":"","startGameChunkId":2,"gameLength":0,"clientAddedLag":0,"clientBackFetchingEnabled":false,"clientBackFetchingFreq":1000,"interestScore":0,"featuredGame":false,"createTime":"","endGameChunkId":-1,"endGameKeyFrameId":-1}"#;
+    const CHUNK_INFO: &str = r#"{"chunkId":1,"availableSince":0,"nextAvailableChunk":0,"keyFrameId":0,"nextChunkId":0,"endStartupChunkId":1,"startGameChunkId":2,"endGameChunkId":1,"duration":30000}"#;
+
+    #[actix_web::test]
+    async fn test_relays_and_records_a_game() {
+        use actix_web::test;
+
+        let mut server = Server::new_async().await;
+        let _version = server
+            .mock("GET", "/observer-mode/rest/consumer/version")
+            .with_body("2.0.0")
+            .create_async()
+            .await;
+        let _metadata = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameMetaData/EUW1/42/0/token",
+            )
+            .with_body(METADATA)
+            .create_async()
+            .await;
+        let chunk = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameDataChunk/EUW1/42/1/token",
+            )
+            .with_body("chunk1")
+            .expect(1)
+            .create_async()
+            .await;
+        let _chunk_info = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getLastChunkInfo/EUW1/42/0/token",
+            )
+            .with_body(CHUNK_INFO)
+            .create_async()
+            .await;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = Library::new(RecordSource::RecordFolder(folder.path().to_path_buf()));
+        let relay = Relay::new(
+            server.url(),
+            folder.path().to_path_buf(),
+            &Config::default(),
+        )
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .app_data(web::Data::new(relay))
+                .service(web::scope("/observer-mode/rest/consumer").configure(configure)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameMetaData/EUW1/42/0/token")
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        assert_eq!(body, METADATA);
+        for _ in 0..2 {
+            let request = test::TestRequest::get()
+                .uri("/observer-mode/rest/consumer/getGameDataChunk/EUW1/42/1/token")
+                .to_request();
+            let body = test::call_and_read_body(&app, request).await;
+            assert_eq!(body, "chunk1");
+        }
+        chunk.assert_async().await;
+
+        let storage = DiskStorage::new(game_folder(folder.path(), "EUW1", "42")).unwrap();
+        let record = StoredRecord::from_json(
+            &String::from_utf8(storage.load_record("42").unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(record.status, RecordStatus::Recording);
+        assert_eq!(record.version, "2.0.0");
+        assert_eq!(record.game_data_chunks, vec![1]);
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getLastChunkInfo/EUW1/42/0/token")
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        assert_eq!(body, CHUNK_INFO);
+
+        // Ended games are replayed from the library
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameMetaData/EUW1/42/0/token")
+            .to_request();
+        let metadata: GameMetaData = test::call_and_read_body_json(&app, request).await;
+        assert!(metadata.game_ended);
+        assert_eq!(metadata.end_game_chunk_id, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_resumes_a_game_recorded_from_another_directory() {
+        use crate::library::tests::store_game;
+        use actix_web::test;
+
+        let mut server = Server::new_async().await;
+        let _version = server
+            .mock("GET", "/observer-mode/rest/consumer/version")
+            .with_body("2.0.0")
+            .create_async()
+            .await;
+        let _chunk = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameDataChunk/KR/6654667050/3/token",
+            )
+            .with_body("chunk3")
+            .create_async()
+            .await;
+
+        let folder = tempfile::tempdir().unwrap();
+        let mut record = store_game(folder.path(), 6654667050);
+        record.status = RecordStatus::Recording;
+        record.storage = StorageDescriptor::Disk {
+            base_path: PathBuf::from("relayed/KR/6654667050"),
+        };
+        let storage = DiskStorage::open(game_folder(folder.path(), "KR", "6654667050"));
+        storage
+            .store_record(&record.game_id, serde_json::to_vec(&record).unwrap())
+            .unwrap();
+        let library = Library::new(RecordSource::RecordFolder(folder.path().to_path_buf()));
+        let relay = Relay::new(
+            server.url(),
+            folder.path().to_path_buf(),
+            &Config::default(),
+        )
+        .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .app_data(web::Data::new(relay))
+                .service(web::scope("/observer-mode/rest/consumer").configure(configure)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameDataChunk/KR/6654667050/3/token")
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        assert_eq!(body, "chunk3");
+
+        assert_eq!(storage.load_game_data_chunk(3).unwrap(), b"chunk3");
+        let record = StoredRecord::from_json(
+            &String::from_utf8(storage.load_record("6654667050").unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(record.game_data_chunks, vec![1, 2, 3]);
+        assert!(!Path::new("relayed").exists());
+    }
+
+    #[actix_web::test]
+    async fn test_relays_the_end_of_an_ended_game() {
+        use actix_web::test;
+
+        const ENDING_CHUNK_INFO: &str = r#"{"chunkId":2,"availableSince":0,"nextAvailableChunk":0,"keyFrameId":1,"nextChunkId":2,"endStartupChunkId":1,"startGameChunkId":2,"endGameChunkId":2,"duration":30000}"#;
+        let mut server = Server::new_async().await;
+        let _version = server
+            .mock("GET", "/observer-mode/rest/consumer/version")
+            .with_body("2.0.0")
+            .create_async()
+            .await;
+        let _metadata = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameMetaData/EUW1/42/0/token",
+            )
+            .with_body(METADATA)
+            .create_async()
+            .await;
+        let _chunk_info = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getLastChunkInfo/EUW1/42/0/token",
+            )
+            .with_body(ENDING_CHUNK_INFO)
+            .create_async()
+            .await;
+        let _chunk = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameDataChunk/EUW1/42/2/token",
+            )
+            .with_body("chunk2")
+            .create_async()
+            .await;
+        let _keyframe = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getKeyFrame/EUW1/42/1/token",
+            )
+            .with_body("keyframe1")
+            .create_async()
+            .await;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = Library::new(RecordSource::RecordFolder(folder.path().to_path_buf()));
+        let relay = web::Data::new(
+            Relay::new(
+                server.url(),
+                folder.path().to_path_buf(),
+                &Config::default(),
+            )
+            .unwrap(),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .app_data(relay.clone())
+                .service(web::scope("/observer-mode/rest/consumer").configure(configure)),
+        )
+        .await;
+        let load_record = || {
+            let storage = DiskStorage::new(game_folder(folder.path(), "EUW1", "42")).unwrap();
+            StoredRecord::from_json(&String::from_utf8(storage.load_record("42").unwrap()).unwrap())
+                .unwrap()
+        };
+
+        for uri in [
+            "/observer-mode/rest/consumer/getGameMetaData/EUW1/42/0/token",
+            "/observer-mode/rest/consumer/getLastChunkInfo/EUW1/42/0/token",
+        ] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        // The end of the game is announced before the spectator client fetched it
+        assert_eq!(load_record().status, RecordStatus::Recording);
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameDataChunk/EUW1/42/2/token")
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        assert_eq!(body, "chunk2");
+        assert_eq!(load_record().status, RecordStatus::Recording);
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getKeyFrame/EUW1/42/1/token")
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        assert_eq!(body, "keyframe1");
+        let record = load_record();
+        assert_eq!(record.status, RecordStatus::Complete);
+        assert_eq!(record.game_data_chunks, vec![2]);
+        assert_eq!(record.keyframes, vec![1]);
+        assert!(relay.games.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_follows_only_games_the_upstream_knows() {
+        use actix_web::test;
+
+        let mut server = Server::new_async().await;
+        let _version = server
+            .mock("GET", "/observer-mode/rest/consumer/version")
+            .with_body("2.0.0")
+            .create_async()
+            .await;
+        let _chunk = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameDataChunk/EUW1/42/1/token",
+            )
+            .with_body("chunk1")
+            .create_async()
+            .await;
+        let _unknown = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameDataChunk/EUW1/43/1/token",
+            )
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = Library::new(RecordSource::RecordFolder(folder.path().to_path_buf()));
+        let mut relay = Relay::new(
+            server.url(),
+            folder.path().to_path_buf(),
+            &Config::default(),
+        )
+        .unwrap();
+        relay.idle_timeout = Duration::from_secs(60);
+        let relay = web::Data::new(relay);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .app_data(relay.clone())
+                .service(web::scope("/observer-mode/rest/consumer").configure(configure)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameDataChunk/EUW1/43/1/token")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(relay.games.lock().unwrap().is_empty());
+        assert!(!folder.path().join("EUW1/43").exists());
+
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameDataChunk/EUW1/42/1/token")
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        assert_eq!(body, "chunk1");
+        assert_eq!(relay.games.lock().unwrap().len(), 1);
+
+        // Idle games are forgotten on the next lookup
+        let game = relay.followed("EUW1", 42).unwrap();
+        *game.last_access.lock().unwrap() -= Duration::from_secs(60);
+        assert!(relay.followed("EUW1", 42).is_none());
+        assert!(relay.games.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rate_limit_from_config() {
+        let folder = tempfile::tempdir().unwrap();
+        let config =
+            Config::from_toml("[concurrency]\nrequests_per_second = 2\n", Vec::new()).unwrap();
+        let relay = Relay::new(
+            "http://127.0.0.1:8080".to_string(),
+            folder.path().to_path_buf(),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            relay.rate_limiter.limit(),
+            RateLimit {
+                requests_per_second: 2,
+                max_in_flight: RateLimit::default().max_in_flight,
+            }
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_relayed_games_without_key_get_one_through_the_api() {
+        use actix_web::test;
+
+        let mut server = Server::new_async().await;
+        let version = server
+            .mock("GET", "/observer-mode/rest/consumer/version")
+            .with_body("2.0.0")
+            .expect(1)
+            .create_async()
+            .await;
+        let _metadata = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameMetaData/EUW1/4200/0/token",
+            )
+            .with_body(METADATA.replace(r#""encryptionKey":"key""#, r#""encryptionKey":"""#))
+            .create_async()
+            .await;
+        let _chunks = server
+            .mock(
+                "GET",
+                mockito::Matcher::Regex(
+                    r"^/observer-mode/rest/consumer/getGameDataChunk/EUW1/420[01]/\d+/token$"
+                        .to_string(),
+                ),
+            )
+            .with_body("chunk")
+            .create_async()
+            .await;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = Library::new(RecordSource::RecordFolder(folder.path().to_path_buf()));
+        let relay = web::Data::new(
+            Relay::new(
+                server.url(),
+                folder.path().to_path_buf(),
+                &Config::default(),
+            )
+            .unwrap(),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .app_data(relay.clone())
+                .service(web::scope("/observer-mode/rest/consumer").configure(configure))
+                .service(web::scope("/api").configure(api::configure))
+                .configure(spectate::configure),
+        )
+        .await;
+        let load_record = || {
+            let storage = DiskStorage::new(game_folder(folder.path(), "EUW1", "4200")).unwrap();
+            StoredRecord::from_json(
+                &String::from_utf8(storage.load_record("4200").unwrap()).unwrap(),
+            )
+            .unwrap()
+        };
+        let set_key = |encryption_key: &str| {
+            test::TestRequest::put()
+                .uri("/api/recordings/EUW1/4200/encryption_key")
+                .set_json(serde_json::json!({ "encryption_key": encryption_key }))
+                .to_request()
+        };
+
+        for uri in [
+            "/observer-mode/rest/consumer/getGameMetaData/EUW1/4200/0/token",
+            "/observer-mode/rest/consumer/getGameDataChunk/EUW1/4200/1/token",
+            "/observer-mode/rest/consumer/getGameDataChunk/EUW1/4201/1/token",
+        ] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        }
+        // Asked once for both games
+        version.assert_async().await;
+
+        let request = test::TestRequest::get()
+            .uri("/spectate/EUW1/4200")
+            .insert_header(("Host", "replays.local:8080"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = test::call_service(&app, set_key("not a key")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Kept by the followed game when it saves the record again
+        let chunk_key = b"0123456789abcdef";
+        let encryption_key = crypto::encryption_key("4200", chunk_key).unwrap();
+        let response = test::call_service(&app, set_key(&encryption_key)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let request = test::TestRequest::get()
+            .uri("/observer-mode/rest/consumer/getGameDataChunk/EUW1/4200/2/token")
+            .to_request();
+        test::call_service(&app, request).await;
+        let record = load_record();
+        assert_eq!(record.encryption_key, encryption_key);
+        assert_eq!(record.game_data_chunks, vec![1, 2]);
+
+        let request = test::TestRequest::get()
+            .uri("/spectate/EUW1/4200")
+            .insert_header(("Host", "replays.local:8080"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Set in the library once the relay forgot the game
+        relay.games.lock().unwrap().clear();
+        let encryption_key = crypto::encryption_key("4200", b"fedcba9876543210").unwrap();
+        let response = test::call_service(&app, set_key(&encryption_key)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(load_record().encryption_key, encryption_key);
+    }
+}
@@ -0,0 +1,402 @@
+use crate::error::Error;
+use crate::library::{Library, LibraryRecord};
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder, Result};
+use lol_replay_common::config::ServerConfig;
+use lol_replay_common::models::GameKey;
+use serde::{Deserialize, Serialize};
+
+use std::net::SocketAddr;
+
+pub const DEFAULT_GAME_DIR: &str = r"C:\Riot Games\League of Legends\Game";
+
+// Characters that would end the quoted path or be interpreted by cmd.exe in the script
+const UNSAFE_GAME_DIR_CHARACTERS: &[char] = &['"', '\r', '\n', '%', '&', '|', '<', '>', '^'];
+
+pub fn configure(config: &mut web::ServiceConfig) {
+    config
+        .service(get_launch_command)
+        .service(get_launch_script);
+}
+
+// What the League client needs to spectate a replay served by this server
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LaunchCommand {
+    pub arguments: String,
+    // Windows batch file starting the game client
+    pub script: String,
+}
+
+impl LaunchCommand {
+    // address is the host:port spectator clients reach this server at
+    pub fn new(address: &str, record: &LibraryRecord, game_dir: &str) -> Result<Self, Error> {
+        Self::for_game(
+            address,
+            &record.record.endpoint.platform_id,
+            &record.record.game_id,
+            record.encryption_key()?,
+            game_dir,
+        )
+    }
+
+    // A game served under another id than the one it was recorded with
+    pub fn for_game(
+        address: &str,
+        platform_id: &str,
+        game_id: &str,
+        encryption_key: &str,
+        game_dir: &str,
+    ) -> Result<Self, Error> {
+        check_address(address)?;
+        if game_dir.contains(UNSAFE_GAME_DIR_CHARACTERS) {
+            return Err(Error::BadRequest(format!(
+                "game_dir cannot contain any of {:?}",
+                UNSAFE_GAME_DIR_CHARACTERS
+            )));
+        }
+        let arguments = format!(
+            "spectator {} {} {} {}",
+            address, encryption_key, game_id, platform_id
+        );
+        let script = format!(
+            "@echo off\r\ncd /d \"{}\"\r\nstart \"\" \"League of Legends.exe\" \"{}\" \"-UseRads\" \"-GameBaseDir=..\"\r\n",
+            game_dir, arguments
+        );
+        Ok(LaunchCommand { arguments, script })
+    }
+}
+
+// The Host header ends up in the script, only host[:port] is accepted
+pub fn check_address(address: &str) -> Result<(), Error> {
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => (host, Some(port)),
+        _ => (address, None),
+    };
+    let valid_host = match host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+    {
+        Some(ipv6) => !ipv6.is_empty() && ipv6.chars().all(|c| c.is_ascii_hexdigit() || c == ':'),
+        None => {
+            !host.is_empty()
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        }
+    };
+    let valid_port =
+        port.is_none_or(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()));
+    if valid_host && valid_port {
+        Ok(())
+    } else {
+        Err(Error::BadRequest(format!(
+            "{:?} is not a valid host",
+            address
+        )))
+    }
+}
+
+// Where launch commands send the spectator client of the game
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObserverAddress {
+    // The address the request was sent to, as seen by the client
+    RequestHost,
+    // The host the request was sent to on another port, for plain HTTP bound to every interface
+    RequestHostOnPort(u16),
+    Fixed(String),
+}
+
+impl ObserverAddress {
+    // With TLS the request reached an HTTPS address, which the game client cannot use
+    pub fn from_config(config: &ServerConfig) -> Result<Self, Error> {
+        let tls = config.tls_cert.is_some();
+        let address = match (&config.public_observer_address, &config.http_bind) {
+            (Some(address), _) => address.clone(),
+            (None, Some(http_bind)) if tls => match http_bind.addresses().first() {
+                Some(address) => match address.parse::<SocketAddr>() {
+                    Ok(address) if address.ip().is_unspecified() => {
+                        return Ok(ObserverAddress::RequestHostOnPort(address.port()))
+                    }
+                    _ => address.to_string(),
+                },
+                None => return Ok(ObserverAddress::RequestHost),
+            },
+            _ => return Ok(ObserverAddress::RequestHost),
+        };
+        check_address(&address)?;
+        Ok(ObserverAddress::Fixed(address))
+    }
+}
+
+pub fn launch_address(request: &HttpRequest) -> Result<String, Error> {
+    let address = match request.app_data::<web::Data<ObserverAddress>>() {
+        Some(observer_address) => match observer_address.as_ref() {
+            ObserverAddress::Fixed(address) => address.clone(),
+            ObserverAddress::RequestHost => request.connection_info().host().to_string(),
+            ObserverAddress::RequestHostOnPort(port) => {
+                format!(
+                    "{}:{}",
+                    without_port(request.connection_info().host()),
+                    port
+                )
+            }
+        },
+        None => request.connection_info().host().to_string(),
+    };
+    check_address(&address)?;
+    Ok(address)
+}
+
+// example.com:8443 -> example.com, [::1]:8443 -> [::1]
+fn without_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port))
+            if !name.is_empty()
+                && port.bytes().all(|byte| byte.is_ascii_digit())
+                && (!name.contains(':') || name.ends_with(']')) =>
+        {
+            name
+        }
+        _ => host,
+    }
+}
+
+#[derive(Deserialize)]
+struct LaunchQuery {
+    // Where League of Legends.exe is installed
+    game_dir: Option<String>,
+}
+
+async fn launch_command(
+    request: &HttpRequest,
+    library: web::Data<Library>,
+    game_key: GameKey,
+    query: LaunchQuery,
+) -> Result<LaunchCommand> {
+    let GameKey {
+        game_id,
+        platform_id,
+    } = game_key;
+    let record = web::block(move || library.get(&platform_id, game_id)).await??;
+    let address = launch_address(request)?;
+    let game_dir = query.game_dir.as_deref().unwrap_or(DEFAULT_GAME_DIR);
+    Ok(LaunchCommand::new(&address, &record, game_dir)?)
+}
+
+#[get("/spectate/{platformId}/{gameId}")]
+async fn get_launch_command(
+    request: HttpRequest,
+    library: web::Data<Library>,
+    game_key: web::Path<GameKey>,
+    query: web::Query<LaunchQuery>,
+) -> Result<impl Responder> {
+    let command =
+        launch_command(&request, library, game_key.into_inner(), query.into_inner()).await?;
+    Ok(web::Json(command))
+}
+
+#[get("/spectate/{platformId}/{gameId}/script")]
+async fn get_launch_script(
+    request: HttpRequest,
+    library: web::Data<Library>,
+    game_key: web::Path<GameKey>,
+    query: web::Query<LaunchQuery>,
+) -> Result<impl Responder> {
+    let game_key = game_key.into_inner();
+    let filename = format!("spectate-{}-{}.bat", game_key.platform_id, game_key.game_id);
+    let command = launch_command(&request, library, game_key, query.into_inner()).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-bat")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .body(command.script))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::tests::library_with_game;
+    use actix_web::http::StatusCode;
+    use actix_web::App;
+    use lol_replay_common::config::BindAddresses;
+
+    #[actix_web::test]
+    async fn test_launch_command_points_at_the_server() {
+        use actix_web::test;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .configure(configure),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/spectate/KR/6654667050")
+            .insert_header(("Host", "replays.local:8080"))
+            .to_request();
+        let command: LaunchCommand = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(
+            command.arguments,
+            "spectator replays.local:8080 oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6 6654667050 KR"
+        );
+
+        let request = test::TestRequest::get()
+            .uri("/spectate/KR/6654667050/script?game_dir=D:%5CGames%5CLoL")
+            .insert_header(("Host", "replays.local:8080"))
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let script = String::from_utf8(body.to_vec()).unwrap();
+        assert!(script.contains(r#"cd /d "D:\Games\LoL""#));
+        assert!(script.contains(
+            r#""spectator replays.local:8080 oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6 6654667050 KR""#
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_rejects_what_would_break_the_script() {
+        use actix_web::test;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .configure(configure),
+        )
+        .await;
+
+        for game_dir in [
+            "C:%5CGames%22%26calc",
+            "%25TEMP%25",
+            "C:%0D%0Acalc",
+            "a%5Eb",
+        ] {
+            let request = test::TestRequest::get()
+                .uri(&format!(
+                    "/spectate/KR/6654667050/script?game_dir={}",
+                    game_dir
+                ))
+                .insert_header(("Host", "replays.local:8080"))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", game_dir);
+        }
+
+        for host in [
+            "replays.local\"&calc",
+            "replays.local:80 x",
+            "replays.local:",
+            "",
+        ] {
+            let request = test::TestRequest::get()
+                .uri("/spectate/KR/6654667050/script")
+                .insert_header(("Host", host))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", host);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_launch_command_points_at_plain_http_with_tls() {
+        use actix_web::test;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = web::Data::new(library_with_game(folder.path()));
+        for (http_bind, host, address) in [
+            (
+                "replays.local:8080",
+                "replays.local:8443",
+                "replays.local:8080",
+            ),
+            // Every interface, the game client reaches the host it was given on the HTTP port
+            (
+                "0.0.0.0:8080",
+                "replays.example.com:8443",
+                "replays.example.com:8080",
+            ),
+            ("[::]:8080", "[2001:db8::1]:8443", "[2001:db8::1]:8080"),
+        ] {
+            let config = ServerConfig {
+                bind: Some(BindAddresses::One("replays.local:8443".to_string())),
+                tls_cert: Some("cert.pem".into()),
+                tls_key: Some("key.pem".into()),
+                http_bind: Some(BindAddresses::One(http_bind.to_string())),
+                ..ServerConfig::default()
+            };
+            let app = test::init_service(
+                App::new()
+                    .app_data(library.clone())
+                    .app_data(web::Data::new(
+                        ObserverAddress::from_config(&config).unwrap(),
+                    ))
+                    .configure(configure),
+            )
+            .await;
+
+            let request = test::TestRequest::get()
+                .uri("/spectate/KR/6654667050")
+                .insert_header(("Host", host))
+                .to_request();
+            let command: LaunchCommand = test::call_and_read_body_json(&app, request).await;
+            assert_eq!(
+                command.arguments,
+                format!(
+                    "spectator {} oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6 6654667050 KR",
+                    address
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_observer_address_from_config() {
+        let mut config = ServerConfig {
+            http_bind: Some(BindAddresses::Many(vec![
+                "0.0.0.0:8080".to_string(),
+                "[::]:8080".to_string(),
+            ])),
+            ..ServerConfig::default()
+        };
+        assert_eq!(
+            ObserverAddress::from_config(&config).unwrap(),
+            ObserverAddress::RequestHost
+        );
+
+        config.tls_cert = Some("cert.pem".into());
+        assert_eq!(
+            ObserverAddress::from_config(&config).unwrap(),
+            ObserverAddress::RequestHostOnPort(8080)
+        );
+
+        config.http_bind = Some(BindAddresses::One("192.168.1.2:8080".to_string()));
+        assert_eq!(
+            ObserverAddress::from_config(&config).unwrap(),
+            ObserverAddress::Fixed("192.168.1.2:8080".to_string())
+        );
+
+        config.public_observer_address = Some("replays.example.com:8080".to_string());
+        assert_eq!(
+            ObserverAddress::from_config(&config).unwrap(),
+            ObserverAddress::Fixed("replays.example.com:8080".to_string())
+        );
+
+        config.public_observer_address = Some("replays.example.com:80 x".to_string());
+        assert!(ObserverAddress::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_check_address() {
+        assert!(check_address("replays.local").is_ok());
+        assert!(check_address("127.0.0.1:8080").is_ok());
+        assert!(check_address("[::1]:8080").is_ok());
+        assert!(check_address("replays.local:8080/x").is_err());
+        assert!(check_address("::1").is_err());
+    }
+}
@@ -0,0 +1,18 @@
+use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
+
+use std::io;
+use std::path::Path;
+
+// One acceptor per bind address, built from PEM files
+pub fn acceptor(cert: &Path, key: &Path) -> io::Result<SslAcceptorBuilder> {
+    let mut builder =
+        SslAcceptor::mozilla_intermediate(SslMethod::tls()).map_err(io::Error::other)?;
+    builder
+        .set_private_key_file(key, SslFiletype::PEM)
+        .map_err(io::Error::other)?;
+    builder
+        .set_certificate_chain_file(cert)
+        .map_err(io::Error::other)?;
+    builder.check_private_key().map_err(io::Error::other)?;
+    Ok(builder)
+}
@@ -0,0 +1,221 @@
+use crate::api::{Completeness, RecordingSummary};
+use crate::error::Error;
+use crate::library::Library;
+use crate::spectate::{launch_address, LaunchCommand, DEFAULT_GAME_DIR};
+
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder, Result};
+use lol_replay_common::models::GameKey;
+
+use std::fmt::Write;
+
+// Browser pages for the people watching the replays, built on the same data as the JSON API
+pub fn configure(config: &mut web::ServiceConfig) {
+    config.service(index).service(recording);
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse}\
+td,th{border:1px solid #ccc;padding:.3em .6em;text-align:left}\
+code{background:#eee;padding:.2em}";
+
+fn page(title: &str, body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title>\
+             <style>{}</style></head><body>{}</body></html>\n",
+            escape(title),
+            STYLE,
+            body
+        ))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Milliseconds as h:mm:ss or m:ss
+fn format_duration(duration: Option<u32>) -> String {
+    let Some(duration) = duration else {
+        return "-".to_string();
+    };
+    let seconds = duration / 1000;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[get("/")]
+async fn index(library: web::Data<Library>) -> Result<impl Responder> {
+    let records = web::block(move || library.list()).await??;
+
+    let mut rows = String::new();
+    for record in &records {
+        let summary = RecordingSummary::from(record);
+        let _ = write!(
+            rows,
+            "<tr><td>{platform}</td><td><a href=\"/recordings/{platform}/{game}\">{game}</a></td>\
+             <td>{date}</td><td>{duration}</td><td>{status:?}</td></tr>",
+            platform = escape(&summary.platform_id),
+            game = escape(&summary.game_id),
+            date = escape(summary.start_time.as_deref().unwrap_or("-")),
+            duration = format_duration(summary.duration),
+            status = summary.status,
+        );
+    }
+    let body = if records.is_empty() {
+        "<h1>Recordings</h1><p>No recordings yet.</p>".to_string()
+    } else {
+        format!(
+            "<h1>Recordings</h1><table><tr><th>Platform</th><th>Game id</th><th>Date</th>\
+             <th>Duration</th><th>Status</th></tr>{}</table>",
+            rows
+        )
+    };
+    Ok(page("Recordings", &body))
+}
+
+#[get("/recordings/{platformId}/{gameId}")]
+async fn recording(
+    request: HttpRequest,
+    library: web::Data<Library>,
+    game_key: web::Path<GameKey>,
+) -> Result<impl Responder> {
+    let GameKey {
+        game_id,
+        platform_id,
+    } = game_key.into_inner();
+    let record = web::block(move || library.get(&platform_id, game_id)).await??;
+    let summary = RecordingSummary::from(&record.record);
+    let completeness = Completeness::from(&record.record);
+    let address = launch_address(&request)?;
+    let path = format!(
+        "{}/{}",
+        escape(&summary.platform_id),
+        escape(&summary.game_id)
+    );
+
+    let mut details = vec![
+        ("Platform", summary.platform_id.clone()),
+        ("Game id", summary.game_id.clone()),
+        ("Status", format!("{:?}", summary.status)),
+        ("Duration", format_duration(summary.duration)),
+        ("Game data chunks", summary.game_data_chunks.to_string()),
+        ("Keyframes", summary.keyframes.to_string()),
+        (
+            "Complete",
+            if completeness.complete { "yes" } else { "no" }.to_string(),
+        ),
+    ];
+    if let Some(metadata) = &record.record.metadata {
+        details.extend([
+            ("Start time", metadata.start_time.clone()),
+            ("Create time", metadata.create_time.clone()),
+            ("Spectator version", record.record.version.clone()),
+            (
+                "Game version",
+                record.record.game_version.clone().unwrap_or_default(),
+            ),
+            (
+                "Chunk time interval",
+                format!("{} ms", metadata.chunk_time_interval),
+            ),
+            (
+                "Keyframe time interval",
+                format!("{} ms", metadata.key_frame_time_interval),
+            ),
+            (
+                "End startup chunk",
+                metadata.end_startup_chunk_id.to_string(),
+            ),
+            ("Start game chunk", metadata.start_game_chunk_id.to_string()),
+            ("Delay time", format!("{} ms", metadata.delay_time)),
+            ("Interest score", metadata.interest_score.to_string()),
+            ("Featured game", metadata.featured_game.to_string()),
+        ]);
+    }
+    let mut rows = String::new();
+    for (name, value) in details {
+        let _ = write!(
+            rows,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            name,
+            escape(&value)
+        );
+    }
+
+    let links = match LaunchCommand::new(&address, &record, DEFAULT_GAME_DIR) {
+        Ok(command) => format!(
+            "<h2>Spectate</h2><p><code>{arguments}</code></p>\
+             <p><a href=\"/spectate/{path}/script\">Download the launch script</a></p>\
+             <h2>Export</h2><p><a href=\"/api/recordings/{path}/rofl\">Download the .rofl replay</a></p>",
+            arguments = escape(&command.arguments),
+            path = path,
+        ),
+        Err(error @ Error::MissingEncryptionKey { .. }) => {
+            format!("<h2>Spectate</h2><p>{}</p>", escape(&error.to_string()))
+        }
+        Err(error) => return Err(error.into()),
+    };
+    let body = format!(
+        "<p><a href=\"/\">All recordings</a></p><h1>{platform} {game}</h1><table>{rows}</table>{links}",
+        platform = escape(&summary.platform_id),
+        game = escape(&summary.game_id),
+        rows = rows,
+        links = links,
+    );
+    Ok(page(
+        &format!("{} {}", summary.platform_id, summary.game_id),
+        &body,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::tests::library_with_game;
+    use actix_web::App;
+
+    #[actix_web::test]
+    async fn test_pages_list_and_describe_recordings() {
+        use actix_web::test;
+
+        let folder = tempfile::tempdir().unwrap();
+        let library = library_with_game(folder.path());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(library))
+                .configure(configure),
+        )
+        .await;
+
+        let request = test::TestRequest::get().uri("/").to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#"<a href="/recordings/KR/6654667050">6654667050</a>"#));
+        assert!(body.contains("<td>1:00</td><td>Complete</td>"));
+
+        let request = test::TestRequest::get()
+            .uri("/recordings/KR/6654667050")
+            .insert_header(("Host", "replays.local:8080"))
+            .to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("<code>spectator replays.local:8080 oLdKikj4AOrw0c4+bR2C+XqOZ2z+Pvi6 6654667050 KR</code>"));
+        assert!(body.contains(r#"href="/api/recordings/KR/6654667050/rofl""#));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(None), "-");
+        assert_eq!(format_duration(Some(61_500)), "1:01");
+        assert_eq!(format_duration(Some(3_725_000)), "1:02:05");
+    }
+}